@@ -14,7 +14,7 @@ fn main() {
 
     let mut size = window.inner_size();
     let mut swap_chain = d3d12
-        .create_window_swap_chain(&window, size.width, size.height)
+        .create_window_swap_chain(&window, size.width, size.height, Default::default())
         .unwrap();
 
     event_loop.set_control_flow(ControlFlow::Wait);
@@ -18,11 +18,11 @@ fn main() {
         .with_no_redirection_bitmap(true)
         .build(&event_loop)
         .unwrap();
-    let target = CompositionTarget::with_window(&window).unwrap();
+    let target = CompositionTarget::with_transparent_window(&window, false).unwrap();
 
     let mut size = window.inner_size();
     let mut swap_chain = composition
-        .create_swap_chain(size.width, size.height)
+        .create_swap_chain(size.width, size.height, Default::default())
         .unwrap();
 
     let surface = target.create_surface(&swap_chain).unwrap().unwrap();
@@ -96,7 +96,7 @@ fn main() {
 }
 
 fn draw(canvas: &Canvas, size: PhysicalSize<u32>) {
-    canvas.clear(colors::BLACK);
+    canvas.clear(colors::TRANSPARENT);
 
     canvas.draw_circle(
         ((size.width / 2) as i32, (size.height / 2) as i32),
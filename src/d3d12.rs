@@ -1,197 +1,557 @@
+pub(crate) mod adapter;
+pub(crate) mod color_space;
+pub(crate) mod context_options;
+pub(crate) mod debug;
+pub(crate) mod dred;
 pub(crate) mod swap_chain;
 
+use adapter::{AdapterKind, AdapterPreference};
+use context_options::ContextOptions;
+use debug::{DebugOptions, InfoQueue, MessageSeverity};
+use dred::DeviceRemovedReport;
 use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
 use skia_safe::{
     gpu::{
+        self as gpu,
         d3d::{
             BackendContext, ID3D12CommandQueue, ID3D12Device, IDXGIAdapter1, TextureResourceInfo,
         },
-        surfaces, BackendRenderTarget, DirectContext, Protected, SurfaceOrigin, SyncCpu,
+        surfaces, BackendRenderTarget, Budgeted, DirectContext, Protected, SurfaceOrigin, SyncCpu,
     },
-    ColorType, Surface,
+    ColorSpace, ImageInfo, Surface,
 };
 use swap_chain::{
-    swap_chain_desc_composition, swap_chain_desc_hwnd, HwndSwapChain, SwapChain,
-    SwapChainSurfaceArray,
+    hdr,
+    options::{PixelFormat, PresentMode, SwapChainKind, SwapChainOptions},
+    HwndSwapChain, SwapChain, SwapChainSurfaces,
 };
 use windows::{
     core::Interface,
     Win32::{
-        Foundation::HWND,
+        Foundation::{BOOL, HWND},
         Graphics::{
-            Direct3D::D3D_FEATURE_LEVEL_11_0,
-            Direct3D12::{D3D12CreateDevice, D3D12_RESOURCE_STATE_COMMON},
+            Direct3D12::{
+                D3D12_FEATURE_DATA_PROTECTED_RESOURCE_SESSION_SUPPORT,
+                D3D12_FEATURE_PROTECTED_RESOURCE_SESSION_SUPPORT,
+                D3D12_PROTECTED_RESOURCE_SESSION_SUPPORT_FLAG_SUPPORTED,
+                D3D12_RESOURCE_STATE_COMMON,
+            },
             Dxgi::{
-                Common::{DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_STANDARD_MULTISAMPLE_QUALITY_PATTERN},
-                CreateDXGIFactory1, IDXGIFactory4, IDXGISwapChain3, DXGI_ADAPTER_FLAG,
-                DXGI_ADAPTER_FLAG_NONE, DXGI_ADAPTER_FLAG_SOFTWARE,
+                Common::DXGI_STANDARD_MULTISAMPLE_QUALITY_PATTERN, CreateDXGIFactory1,
+                IDXGIFactory4, IDXGIFactory5, IDXGISwapChain3, DXGI_FEATURE_PRESENT_ALLOW_TEARING,
+                DXGI_SWAP_CHAIN_DESC1, DXGI_SWAP_CHAIN_FLAG_HW_PROTECTED,
+                DXGI_SWAP_CHAIN_FLAG_RESTRICTED_CONTENT,
             },
         },
     },
 };
 
-pub struct Backend {
-    factory: IDXGIFactory4,
-    skia_context: OptionalSkiaContext,
+use crate::error::{Error, Result};
+
+#[derive(Debug, Default)]
+pub struct BackendBuilder {
+    settings: ContextSettings,
+    device_event_handler: DeviceEventHandler,
 }
-impl Backend {
-    pub fn new() -> windows::core::Result<Self> {
+impl BackendBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn adapter_preference(mut self, adapter_preference: AdapterPreference) -> Self {
+        self.settings.adapter_preference = adapter_preference;
+        self
+    }
+    pub fn software_fallback(mut self, software_fallback: bool) -> Self {
+        self.settings.software_fallback = software_fallback;
+        self
+    }
+    pub fn debug(mut self, debug: DebugOptions) -> Self {
+        self.settings.debug = Some(debug);
+        self
+    }
+    pub fn device_removed_extended_data(mut self, enable: bool) -> Self {
+        self.settings.device_removed_extended_data = enable;
+        self
+    }
+    pub fn context_options(mut self, context_options: ContextOptions) -> Self {
+        self.settings.context_options = context_options;
+        self
+    }
+    pub fn protected_context(mut self, protected_context: bool) -> Self {
+        self.settings.protected_context = protected_context;
+        self
+    }
+    pub fn on_device_event(mut self, f: impl FnMut(&DeviceEvent) + 'static) -> Self {
+        self.device_event_handler = DeviceEventHandler(Some(Box::new(f)));
+        self
+    }
+    pub fn build(self) -> Result<Backend> {
         let factory: IDXGIFactory4 = unsafe { CreateDXGIFactory1() }?;
 
-        let skia_context = OptionalSkiaContext::new(&factory)?;
+        self.build_with_factory(factory)
+    }
+    pub fn build_from_parts(
+        mut self,
+        factory: IDXGIFactory4,
+        adapter: IDXGIAdapter1,
+        device: ID3D12Device,
+        queue: ID3D12CommandQueue,
+    ) -> Result<Backend> {
+        self.settings.device_parts = Some(DeviceParts {
+            adapter,
+            device,
+            queue,
+        });
+        self.build_with_factory(factory)
+    }
+    fn build_with_factory(self, factory: IDXGIFactory4) -> Result<Backend> {
+        let skia_context = OptionalSkiaContext::new(&factory, &self.settings)?;
 
-        Ok(Self {
+        Ok(Backend {
             factory,
+            settings: self.settings,
             skia_context,
+            context_generation: 0,
+            device_removed_report: None,
+            device_event_handler: self.device_event_handler,
         })
     }
+}
+
+#[derive(Debug, Default)]
+struct ContextSettings {
+    adapter_preference: AdapterPreference,
+    software_fallback: bool,
+    debug: Option<DebugOptions>,
+    device_removed_extended_data: bool,
+    device_parts: Option<DeviceParts>,
+    context_options: ContextOptions,
+    protected_context: bool,
+}
+
+#[derive(Debug, Clone)]
+struct DeviceParts {
+    adapter: IDXGIAdapter1,
+    device: ID3D12Device,
+    queue: ID3D12CommandQueue,
+}
+
+#[derive(Debug, Clone)]
+pub enum DeviceEvent {
+    Lost(Error),
+    Recreated,
+}
+
+type DeviceEventCallback = Box<dyn FnMut(&DeviceEvent)>;
+
+#[derive(Default)]
+struct DeviceEventHandler(Option<DeviceEventCallback>);
+impl DeviceEventHandler {
+    fn emit(&mut self, event: DeviceEvent) {
+        if let Some(f) = &mut self.0 {
+            f(&event);
+        }
+    }
+}
+impl std::fmt::Debug for DeviceEventHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("DeviceEventHandler")
+            .field(&self.0.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+pub struct Backend {
+    factory: IDXGIFactory4,
+    settings: ContextSettings,
+    skia_context: OptionalSkiaContext,
+    context_generation: u64,
+    device_removed_report: Option<DeviceRemovedReport>,
+    device_event_handler: DeviceEventHandler,
+}
+impl Backend {
+    pub fn new() -> Result<Self> {
+        Self::builder().build()
+    }
+    pub fn builder() -> BackendBuilder {
+        BackendBuilder::new()
+    }
+    pub fn from_parts(
+        factory: IDXGIFactory4,
+        adapter: IDXGIAdapter1,
+        device: ID3D12Device,
+        queue: ID3D12CommandQueue,
+    ) -> Result<Self> {
+        Self::builder().build_from_parts(factory, adapter, device, queue)
+    }
+    pub fn replace_parts(
+        &mut self,
+        adapter: IDXGIAdapter1,
+        device: ID3D12Device,
+        queue: ID3D12CommandQueue,
+    ) -> Result<()> {
+        self.release_context();
+        self.settings.device_parts = Some(DeviceParts {
+            adapter,
+            device,
+            queue,
+        });
+        self.recreate_context_if_needed()?;
+        Ok(())
+    }
     pub fn release_context(&mut self) {
+        self.capture_device_removed_report();
         self.skia_context.release();
     }
-    pub fn recreate_context_if_needed(&mut self) -> windows::core::Result<bool> {
-        self.skia_context.recreate_if_needed(&self.factory)
+    pub fn recreate_context_if_needed(&mut self) -> Result<bool> {
+        let recreated = self
+            .skia_context
+            .recreate_if_needed(&self.factory, &self.settings)?;
+
+        if recreated {
+            self.context_generation += 1;
+            self.device_event_handler.emit(DeviceEvent::Recreated);
+        }
+        Ok(recreated)
+    }
+    pub fn with_direct_context<R>(&mut self, f: impl FnOnce(&mut DirectContext) -> R) -> Result<R> {
+        Ok(f(&mut self.skia_context.get_mut()?.direct_context))
+    }
+    pub fn with_backend_context<R>(&self, f: impl FnOnce(&BackendContext) -> R) -> Result<R> {
+        Ok(f(&self.skia_context.get()?.backend_context))
+    }
+    pub fn adapter(&self) -> Result<IDXGIAdapter1> {
+        self.with_backend_context(|backend_context| backend_context.adapter.clone())
+    }
+    pub fn device(&self) -> Result<ID3D12Device> {
+        self.with_backend_context(|backend_context| backend_context.device.clone())
+    }
+    pub fn queue(&self) -> Result<ID3D12CommandQueue> {
+        self.with_backend_context(|backend_context| backend_context.queue.clone())
+    }
+    pub fn context_options(&self) -> &ContextOptions {
+        &self.settings.context_options
+    }
+    // Options that only take effect at creation apply the next time the context is recreated.
+    pub fn set_context_options(&mut self, context_options: ContextOptions) {
+        if let Ok(skia_context) = self.skia_context.get_mut() {
+            context_options.apply(&mut skia_context.direct_context);
+        }
+        self.settings.context_options = context_options;
+    }
+    pub fn set_device_event_handler(&mut self, f: impl FnMut(&DeviceEvent) + 'static) {
+        self.device_event_handler = DeviceEventHandler(Some(Box::new(f)));
+    }
+    pub(crate) fn device_lost(&mut self, error: Error) {
+        self.release_context();
+        self.device_event_handler.emit(DeviceEvent::Lost(error));
+    }
+    pub(crate) fn is_current_context(&self, generation: u64) -> bool {
+        self.skia_context.0.is_some() && self.context_generation == generation
+    }
+    pub fn adapter_kind(&self) -> Result<AdapterKind> {
+        Ok(self.skia_context.get()?.adapter_kind)
     }
     pub fn create_window_swap_chain<W: HasRawWindowHandle>(
         &mut self,
         window: &W,
         width: u32,
         height: u32,
-    ) -> windows::core::Result<HwndSwapChain> {
-        self.create_raw_window_handle_swap_chain(window.raw_window_handle(), width, height)
+        options: SwapChainOptions,
+    ) -> Result<HwndSwapChain> {
+        self.create_raw_window_handle_swap_chain(window.raw_window_handle(), width, height, options)
     }
     pub fn create_raw_window_handle_swap_chain(
         &mut self,
         window_handle: RawWindowHandle,
         width: u32,
         height: u32,
-    ) -> windows::core::Result<HwndSwapChain> {
+        options: SwapChainOptions,
+    ) -> Result<HwndSwapChain> {
         let hwnd = match window_handle {
             RawWindowHandle::Win32(window_handle) => HWND(window_handle.hwnd as _),
-            _ => panic!("not win32"),
+            _ => return Err(Error::UnsupportedWindowHandle),
         };
-        self.create_hwnd_swap_chain(hwnd, width, height)
+        self.create_hwnd_swap_chain(hwnd, width, height, options)
     }
     pub fn create_hwnd_swap_chain(
         &mut self,
         hwnd: HWND,
         width: u32,
         height: u32,
-    ) -> windows::core::Result<HwndSwapChain> {
-        Ok(HwndSwapChain::new(
-            hwnd,
-            self.create_swap_chain_for_hwnd(hwnd, width, height)?,
-        ))
+        options: SwapChainOptions,
+    ) -> Result<HwndSwapChain> {
+        let swap_chain = self.create_swap_chain_for_hwnd(hwnd, width, height, &options)?;
+
+        Ok(HwndSwapChain::new(hwnd, options, swap_chain))
     }
     pub fn create_swap_chain_for_hwnd(
         &mut self,
         hwnd: HWND,
         width: u32,
         height: u32,
-    ) -> windows::core::Result<SwapChain> {
-        let swap_chain: IDXGISwapChain3 = unsafe {
+        options: &SwapChainOptions,
+    ) -> Result<SwapChain> {
+        options.validate(SwapChainKind::Hwnd)?;
+
+        let present_mode = self.supported_present_mode(options.present_mode);
+        let desc = options.desc(
+            SwapChainKind::Hwnd,
+            width,
+            height,
+            self.swap_chain_flags() | present_mode.swap_chain_flags(),
+        );
+        let swap_chain = unsafe {
             self.factory.CreateSwapChainForHwnd(
-                &self.skia_context.unwrap_ref().backend_context.queue,
+                &self.skia_context.get()?.backend_context.queue,
                 hwnd,
-                &swap_chain_desc_hwnd(width, height),
+                &desc,
                 None,
                 None,
             )
-        }?
-        .cast()?;
+        }?;
 
-        let surfaces = self.create_swap_chain_surfaces(&swap_chain, width, height);
-
-        Ok(SwapChain::new(swap_chain, surfaces))
+        self.create_swap_chain(swap_chain.cast()?, desc, options, present_mode, Some(hwnd))
     }
     pub fn create_swap_chain_for_composition(
         &mut self,
         width: u32,
         height: u32,
-    ) -> windows::core::Result<SwapChain> {
-        let swap_chain: IDXGISwapChain3 = unsafe {
+        options: &SwapChainOptions,
+    ) -> Result<SwapChain> {
+        options.validate(SwapChainKind::Composition)?;
+
+        let present_mode = self.supported_present_mode(options.present_mode);
+        let desc = options.desc(
+            SwapChainKind::Composition,
+            width,
+            height,
+            self.swap_chain_flags() | present_mode.swap_chain_flags(),
+        );
+        let swap_chain = unsafe {
             self.factory.CreateSwapChainForComposition(
-                &self.skia_context.unwrap_ref().backend_context.queue,
-                &swap_chain_desc_composition(width, height),
+                &self.skia_context.get()?.backend_context.queue,
+                &desc,
                 None,
             )
-        }?
-        .cast()?;
+        }?;
 
-        let surfaces = self.create_swap_chain_surfaces(&swap_chain, width, height);
+        self.create_swap_chain(swap_chain.cast()?, desc, options, present_mode, None)
+    }
+    fn create_swap_chain(
+        &mut self,
+        swap_chain: IDXGISwapChain3,
+        desc: DXGI_SWAP_CHAIN_DESC1,
+        options: &SwapChainOptions,
+        present_mode: PresentMode,
+        hwnd: Option<HWND>,
+    ) -> Result<SwapChain> {
+        hdr::apply(&swap_chain, options.hdr, options.hdr_metadata.as_ref())?;
 
-        Ok(SwapChain::new(swap_chain, surfaces))
+        let color_space = options.skia_color_space(hwnd)?;
+        let surfaces = self.create_swap_chain_surfaces(&swap_chain, &desc, color_space.clone())?;
+
+        SwapChain::new(
+            swap_chain,
+            surfaces,
+            desc,
+            color_space,
+            present_mode,
+            options,
+            self.context_generation,
+        )
+    }
+    pub fn supports_tearing(&self) -> bool {
+        let Ok(factory) = self.factory.cast::<IDXGIFactory5>() else {
+            return false;
+        };
+        let mut allow_tearing = BOOL::default();
+        unsafe {
+            factory.CheckFeatureSupport(
+                DXGI_FEATURE_PRESENT_ALLOW_TEARING,
+                &mut allow_tearing as *mut _ as _,
+                std::mem::size_of_val(&allow_tearing) as _,
+            )
+        }
+        .is_ok()
+            && allow_tearing.as_bool()
+    }
+    fn supported_present_mode(&self, present_mode: PresentMode) -> PresentMode {
+        if present_mode == PresentMode::Tearing && !self.supports_tearing() {
+            log::warn!("tearing is not supported, falling back to PresentMode::Immediate");
+            PresentMode::Immediate
+        } else {
+            present_mode
+        }
+    }
+    fn swap_chain_flags(&self) -> u32 {
+        if self.settings.protected_context {
+            (DXGI_SWAP_CHAIN_FLAG_HW_PROTECTED.0 | DXGI_SWAP_CHAIN_FLAG_RESTRICTED_CONTENT.0) as _
+        } else {
+            0
+        }
+    }
+    fn protected(&self) -> Protected {
+        if self.settings.protected_context {
+            Protected::Yes
+        } else {
+            Protected::No
+        }
+    }
+    pub fn is_protected(&self) -> bool {
+        self.settings.protected_context
+    }
+    pub fn supports_protected_content(&self) -> Result<bool> {
+        Ok(supports_protected_content(
+            &self.skia_context.get()?.backend_context.device,
+        ))
+    }
+    pub fn create_offscreen_surface(&mut self, image_info: &ImageInfo) -> Result<Surface> {
+        let protected = self.settings.protected_context;
+
+        gpu::surfaces::render_target(
+            &mut self.skia_context.get_mut()?.direct_context,
+            Budgeted::Yes,
+            image_info,
+            None,
+            SurfaceOrigin::TopLeft,
+            None,
+            false,
+            protected,
+        )
+        .ok_or(Error::SurfaceCreationFailed)
     }
     pub(crate) fn create_swap_chain_surfaces(
         &mut self,
         swap_chain: &IDXGISwapChain3,
-        width: u32,
-        height: u32,
-    ) -> SwapChainSurfaceArray {
-        std::array::from_fn(|i| {
-            let resource = unsafe { swap_chain.GetBuffer(i as u32).unwrap() };
+        desc: &DXGI_SWAP_CHAIN_DESC1,
+        color_space: Option<ColorSpace>,
+    ) -> Result<SwapChainSurfaces> {
+        let protected = self.protected();
+        let format = PixelFormat::from_dxgi(desc.Format).ok_or(Error::SurfaceWrapFailed)?;
+
+        let mut surfaces = Vec::with_capacity(desc.BufferCount as _);
+        for i in 0..desc.BufferCount {
+            let resource = unsafe { swap_chain.GetBuffer(i) }?;
 
             let backend_render_target = BackendRenderTarget::new_d3d(
-                (width.try_into().unwrap(), height.try_into().unwrap()),
+                (
+                    desc.Width
+                        .try_into()
+                        .map_err(|_| Error::SurfaceWrapFailed)?,
+                    desc.Height
+                        .try_into()
+                        .map_err(|_| Error::SurfaceWrapFailed)?,
+                ),
                 &TextureResourceInfo {
                     resource,
                     alloc: None,
                     resource_state: D3D12_RESOURCE_STATE_COMMON,
-                    format: DXGI_FORMAT_R8G8B8A8_UNORM,
+                    format: format.to_dxgi(),
                     sample_count: 1,
                     level_count: 0,
                     sample_quality_pattern: DXGI_STANDARD_MULTISAMPLE_QUALITY_PATTERN,
-                    protected: Protected::No,
+                    protected,
                 },
             );
 
             let surface = surfaces::wrap_backend_render_target(
-                &mut self.skia_context.unwrap_mut().direct_context,
+                &mut self.skia_context.get_mut()?.direct_context,
                 &backend_render_target,
                 SurfaceOrigin::TopLeft,
-                ColorType::RGBA8888,
-                None,
+                format.color_type(),
+                color_space.clone(),
                 None,
             )
-            .unwrap();
+            .ok_or(Error::SurfaceWrapFailed)?;
 
-            (surface, backend_render_target)
-        })
+            surfaces.push((surface, backend_render_target));
+        }
+        Ok(surfaces)
     }
     pub(crate) fn flush_and_submit_surface(
         &mut self,
         surface: &mut Surface,
         sync_cpu: impl Into<Option<SyncCpu>>,
-    ) {
+    ) -> Result<()> {
         self.skia_context
-            .unwrap_mut()
-            .flush_and_submit_surface(surface, sync_cpu)
+            .get_mut()?
+            .flush_and_submit_surface(surface, sync_cpu);
+        self.drain_debug_messages();
+        Ok(())
+    }
+    pub fn drain_debug_messages(&self) {
+        if let Some(info_queue) = self
+            .skia_context
+            .get()
+            .ok()
+            .and_then(|skia_context| skia_context.info_queue.as_ref())
+        {
+            info_queue.drain();
+        }
+    }
+    pub fn set_break_on_severity(&self, severity: MessageSeverity, enable: bool) -> Result<()> {
+        match &self.skia_context.get()?.info_queue {
+            Some(info_queue) => info_queue.set_break_on_severity(severity, enable),
+            None => Ok(()),
+        }
+    }
+    pub fn get_device_removed_reason(&self) -> Result<()> {
+        self.skia_context.get()?.get_device_removed_reason()
     }
-    pub fn get_device_removed_reason(&self) -> windows::core::Result<()> {
-        self.skia_context.unwrap_ref().get_device_removed_reason()
+    pub(crate) fn device_error(&mut self, error: Error) -> Error {
+        self.drain_debug_messages();
+        match error {
+            Error::DeviceRemoved(_) => {
+                self.capture_device_removed_report();
+                self.get_device_removed_reason().err().unwrap_or(error)
+            }
+            error => error,
+        }
+    }
+    fn capture_device_removed_report(&mut self) {
+        if let Some(report) = self
+            .skia_context
+            .get()
+            .ok()
+            .and_then(SkiaContext::device_removed_report)
+        {
+            self.device_removed_report = Some(report);
+        }
+    }
+    pub fn take_device_removed_report(&mut self) -> Option<DeviceRemovedReport> {
+        self.capture_device_removed_report();
+        self.device_removed_report.take()
     }
     pub fn cleanup(&mut self) {
-        self.skia_context.unwrap_mut().cleanup()
+        if let Ok(skia_context) = self.skia_context.get_mut() {
+            skia_context.cleanup()
+        }
     }
 }
 
 struct OptionalSkiaContext(Option<SkiaContext>);
 impl OptionalSkiaContext {
-    fn new(factory: &IDXGIFactory4) -> windows::core::Result<Self> {
-        Ok(Self(Some(SkiaContext::new(factory)?)))
+    fn new(factory: &IDXGIFactory4, settings: &ContextSettings) -> Result<Self> {
+        Ok(Self(Some(SkiaContext::new(factory, settings)?)))
     }
-    fn recreate_if_needed(&mut self, factory: &IDXGIFactory4) -> windows::core::Result<bool> {
+    fn recreate_if_needed(
+        &mut self,
+        factory: &IDXGIFactory4,
+        settings: &ContextSettings,
+    ) -> Result<bool> {
         if self.0.is_none() {
-            self.0 = Some(SkiaContext::new(factory)?);
+            self.0 = Some(SkiaContext::new(factory, settings)?);
             Ok(true)
         } else {
             Ok(false)
         }
     }
-    fn unwrap_ref(&self) -> &SkiaContext {
-        self.0.as_ref().unwrap()
+    fn get(&self) -> Result<&SkiaContext> {
+        self.0.as_ref().ok_or(Error::ContextLost)
     }
-    fn unwrap_mut(&mut self) -> &mut SkiaContext {
-        self.0.as_mut().unwrap()
+    fn get_mut(&mut self) -> Result<&mut SkiaContext> {
+        self.0.as_mut().ok_or(Error::ContextLost)
     }
     fn release(&mut self) {
         self.0 = None;
@@ -201,24 +561,77 @@ impl OptionalSkiaContext {
 struct SkiaContext {
     backend_context: BackendContext,
     direct_context: DirectContext,
+    adapter_kind: AdapterKind,
+    info_queue: Option<InfoQueue>,
 }
 impl SkiaContext {
-    fn new(factory: &IDXGIFactory4) -> windows::core::Result<Self> {
-        let (adapter, device) = get_hardware_adapter_and_device(&factory)?;
-        let queue: ID3D12CommandQueue = unsafe { device.CreateCommandQueue(&Default::default()) }?;
+    fn new(factory: &IDXGIFactory4, settings: &ContextSettings) -> Result<Self> {
+        let (adapter, device, queue, adapter_kind) = match &settings.device_parts {
+            // Caller-owned objects are never recreated here; a removed device has to be
+            // replaced through `Backend::replace_parts`.
+            Some(parts) => {
+                unsafe { parts.device.GetDeviceRemovedReason() }
+                    .map_err(|error| Error::DeviceRemoved(error.code()))?;
+
+                let adapter_kind = adapter::adapter_kind(&parts.adapter)?;
+                (
+                    parts.adapter.clone(),
+                    parts.device.clone(),
+                    parts.queue.clone(),
+                    adapter_kind,
+                )
+            }
+            None => {
+                if let Some(debug) = &settings.debug {
+                    debug::enable_debug_layer(debug)?;
+                }
+                if settings.device_removed_extended_data {
+                    dred::enable()?;
+                }
+
+                let (adapter, device, adapter_kind) = adapter::select_adapter_and_device(
+                    factory,
+                    &settings.adapter_preference,
+                    settings.software_fallback,
+                )?;
+                let queue: ID3D12CommandQueue =
+                    unsafe { device.CreateCommandQueue(&Default::default()) }?;
+
+                (adapter, device, queue, adapter_kind)
+            }
+        };
+
+        if settings.protected_context && !supports_protected_content(&device) {
+            return Err(Error::ProtectedContentUnsupported);
+        }
+
+        let info_queue = settings
+            .debug
+            .as_ref()
+            .and_then(|debug| InfoQueue::new(&device, debug).ok());
 
         let backend_context = BackendContext {
             adapter,
             device,
             queue,
             memory_allocator: None,
-            protected_context: Protected::No,
+            protected_context: if settings.protected_context {
+                Protected::Yes
+            } else {
+                Protected::No
+            },
         };
-        let direct_context = unsafe { DirectContext::new_d3d(&backend_context, None) }.unwrap();
+        let mut direct_context = unsafe {
+            DirectContext::new_d3d(&backend_context, &settings.context_options.to_skia())
+        }
+        .ok_or(Error::ContextCreationFailed)?;
+        settings.context_options.apply(&mut direct_context);
 
         Ok(Self {
             backend_context,
             direct_context,
+            adapter_kind,
+            info_queue,
         })
     }
     pub(crate) fn flush_and_submit_surface(
@@ -229,8 +642,13 @@ impl SkiaContext {
         self.direct_context
             .flush_and_submit_surface(surface, sync_cpu);
     }
-    pub fn get_device_removed_reason(&self) -> windows::core::Result<()> {
+    pub fn get_device_removed_reason(&self) -> Result<()> {
         unsafe { self.backend_context.device.GetDeviceRemovedReason() }
+            .map_err(|error| Error::DeviceRemoved(error.code()))
+    }
+    fn device_removed_report(&self) -> Option<DeviceRemovedReport> {
+        let reason = unsafe { self.backend_context.device.GetDeviceRemovedReason() }.err()?;
+        Some(dred::report(&self.backend_context.device, reason.code()))
     }
     pub fn cleanup(&mut self) {
         self.direct_context
@@ -238,25 +656,15 @@ impl SkiaContext {
     }
 }
 
-fn get_hardware_adapter_and_device(
-    factory: &IDXGIFactory4,
-) -> windows::core::Result<(IDXGIAdapter1, ID3D12Device)> {
-    for i in 0.. {
-        let adapter = unsafe { factory.EnumAdapters1(i) }?;
-
-        let mut adapter_desc = Default::default();
-        unsafe { adapter.GetDesc1(&mut adapter_desc) }?;
-
-        if (DXGI_ADAPTER_FLAG(adapter_desc.Flags as _) & DXGI_ADAPTER_FLAG_SOFTWARE)
-            != DXGI_ADAPTER_FLAG_NONE
-        {
-            continue; // Don't select the Basic Render Driver adapter.
-        }
-
-        let mut device = None;
-        if unsafe { D3D12CreateDevice(&adapter, D3D_FEATURE_LEVEL_11_0, &mut device) }.is_ok() {
-            return Ok((adapter, device.unwrap()));
-        }
+fn supports_protected_content(device: &ID3D12Device) -> bool {
+    let mut data = D3D12_FEATURE_DATA_PROTECTED_RESOURCE_SESSION_SUPPORT::default();
+    unsafe {
+        device.CheckFeatureSupport(
+            D3D12_FEATURE_PROTECTED_RESOURCE_SESSION_SUPPORT,
+            &mut data as *mut _ as _,
+            std::mem::size_of_val(&data) as _,
+        )
     }
-    unreachable!()
+    .is_ok()
+        && (data.Support.0 & D3D12_PROTECTED_RESOURCE_SESSION_SUPPORT_FLAG_SUPPORTED.0) != 0
 }
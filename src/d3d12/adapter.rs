@@ -0,0 +1,336 @@
+use std::fmt;
+
+use skia_safe::gpu::d3d::{ID3D12Device, IDXGIAdapter1};
+use windows::{
+    core::Interface,
+    Win32::{
+        Foundation::LUID,
+        Graphics::{
+            Direct3D::{
+                D3D_FEATURE_LEVEL, D3D_FEATURE_LEVEL_11_0, D3D_FEATURE_LEVEL_11_1,
+                D3D_FEATURE_LEVEL_12_0, D3D_FEATURE_LEVEL_12_1, D3D_FEATURE_LEVEL_12_2,
+            },
+            Direct3D12::{
+                D3D12CreateDevice, D3D12_FEATURE_DATA_FEATURE_LEVELS, D3D12_FEATURE_FEATURE_LEVELS,
+            },
+            Dxgi::{
+                CreateDXGIFactory1, IDXGIFactory4, IDXGIFactory6, DXGI_ADAPTER_DESC1,
+                DXGI_ADAPTER_FLAG, DXGI_ADAPTER_FLAG_NONE, DXGI_ADAPTER_FLAG_SOFTWARE,
+                DXGI_ERROR_NOT_FOUND, DXGI_GPU_PREFERENCE, DXGI_GPU_PREFERENCE_HIGH_PERFORMANCE,
+                DXGI_GPU_PREFERENCE_MINIMUM_POWER,
+            },
+        },
+    },
+};
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdapterKind {
+    Hardware,
+    Software,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdapterInfo {
+    pub description: String,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub luid: LUID,
+    pub dedicated_video_memory: usize,
+    pub software: bool,
+    pub max_feature_level: Option<D3D_FEATURE_LEVEL>,
+}
+impl AdapterInfo {
+    pub fn kind(&self) -> AdapterKind {
+        if self.software {
+            AdapterKind::Software
+        } else {
+            AdapterKind::Hardware
+        }
+    }
+}
+impl From<&DXGI_ADAPTER_DESC1> for AdapterInfo {
+    fn from(desc: &DXGI_ADAPTER_DESC1) -> Self {
+        let description_len = desc
+            .Description
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(desc.Description.len());
+
+        Self {
+            description: String::from_utf16_lossy(&desc.Description[..description_len]),
+            vendor_id: desc.VendorId,
+            device_id: desc.DeviceId,
+            luid: desc.AdapterLuid,
+            dedicated_video_memory: desc.DedicatedVideoMemory,
+            software: (DXGI_ADAPTER_FLAG(desc.Flags as _) & DXGI_ADAPTER_FLAG_SOFTWARE)
+                != DXGI_ADAPTER_FLAG_NONE,
+            max_feature_level: None,
+        }
+    }
+}
+
+pub type AdapterPredicate = Box<dyn Fn(&AdapterInfo) -> bool>;
+pub type AdapterSelector = Box<dyn Fn(&[AdapterInfo]) -> Option<usize>>;
+
+#[derive(Default)]
+pub enum AdapterPreference {
+    #[default]
+    Default,
+    HighPerformance,
+    MinimumPower,
+    Luid(LUID),
+    Matching(AdapterPredicate),
+    Custom(AdapterSelector),
+}
+impl AdapterPreference {
+    pub fn adapter(info: &AdapterInfo) -> Self {
+        Self::Luid(info.luid)
+    }
+    pub fn vendor(vendor_id: u32) -> Self {
+        Self::Matching(Box::new(move |info| info.vendor_id == vendor_id))
+    }
+    pub fn name_contains(name: impl Into<String>) -> Self {
+        let name = name.into().to_lowercase();
+        Self::Matching(Box::new(move |info| {
+            info.description.to_lowercase().contains(&name)
+        }))
+    }
+    fn gpu_preference(&self) -> Option<DXGI_GPU_PREFERENCE> {
+        match self {
+            Self::HighPerformance => Some(DXGI_GPU_PREFERENCE_HIGH_PERFORMANCE),
+            Self::MinimumPower => Some(DXGI_GPU_PREFERENCE_MINIMUM_POWER),
+            _ => None,
+        }
+    }
+    // Indices into `adapters` in the order device creation should be attempted. An explicit
+    // LUID may name a software adapter picked from `enumerate_adapters`.
+    pub(crate) fn candidates(&self, adapters: &[AdapterInfo]) -> Vec<usize> {
+        let hardware = || (0..adapters.len()).filter(|&i| !adapters[i].software);
+
+        match self {
+            Self::Default | Self::HighPerformance | Self::MinimumPower => hardware().collect(),
+            Self::Luid(luid) => (0..adapters.len())
+                .filter(|&i| adapters[i].luid == *luid)
+                .collect(),
+            Self::Matching(predicate) => hardware().filter(|&i| predicate(&adapters[i])).collect(),
+            Self::Custom(select) => select(adapters)
+                .filter(|&i| i < adapters.len())
+                .into_iter()
+                .collect(),
+        }
+    }
+}
+impl fmt::Debug for AdapterPreference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => f.write_str("Default"),
+            Self::HighPerformance => f.write_str("HighPerformance"),
+            Self::MinimumPower => f.write_str("MinimumPower"),
+            Self::Luid(luid) => f.debug_tuple("Luid").field(luid).finish(),
+            Self::Matching(_) => f.write_str("Matching(..)"),
+            Self::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+pub(crate) fn enumerate(
+    factory: &IDXGIFactory4,
+    gpu_preference: Option<DXGI_GPU_PREFERENCE>,
+) -> Result<Vec<(IDXGIAdapter1, AdapterInfo)>> {
+    let factory6 = gpu_preference.and_then(|_| factory.cast::<IDXGIFactory6>().ok());
+
+    let mut adapters = Vec::new();
+    for i in 0.. {
+        let adapter = match (&factory6, gpu_preference) {
+            (Some(factory6), Some(gpu_preference)) => unsafe {
+                factory6.EnumAdapterByGpuPreference::<IDXGIAdapter1>(i, gpu_preference)
+            },
+            _ => unsafe { factory.EnumAdapters1(i) },
+        };
+        let adapter = match adapter {
+            Ok(adapter) => adapter,
+            Err(error) if error.code() == DXGI_ERROR_NOT_FOUND => break,
+            Err(error) => return Err(error.into()),
+        };
+
+        let mut adapter_desc = Default::default();
+        unsafe { adapter.GetDesc1(&mut adapter_desc) }?;
+
+        adapters.push((adapter, AdapterInfo::from(&adapter_desc)));
+    }
+    Ok(adapters)
+}
+
+pub fn enumerate_adapters() -> Result<Vec<AdapterInfo>> {
+    let factory: IDXGIFactory4 = unsafe { CreateDXGIFactory1() }?;
+
+    // Creating a device powers up the GPU, so only the explicit listing probes feature levels.
+    Ok(enumerate(&factory, None)?
+        .into_iter()
+        .map(|(adapter, mut info)| {
+            info.max_feature_level =
+                create_device(&adapter).and_then(|device| max_feature_level(&device));
+            info
+        })
+        .collect())
+}
+
+pub(crate) fn select_adapter_and_device(
+    factory: &IDXGIFactory4,
+    preference: &AdapterPreference,
+    software_fallback: bool,
+) -> Result<(IDXGIAdapter1, ID3D12Device, AdapterKind)> {
+    let adapters = enumerate(factory, preference.gpu_preference())?;
+    let infos: Vec<_> = adapters.iter().map(|(_, info)| info.clone()).collect();
+
+    for i in preference.candidates(&infos) {
+        let (adapter, info) = &adapters[i];
+
+        if let Some(device) = create_device(adapter) {
+            return Ok((adapter.clone(), device, info.kind()));
+        }
+    }
+
+    if software_fallback {
+        let adapter: IDXGIAdapter1 = unsafe { factory.EnumWarpAdapter() }?;
+        if let Some(device) = create_device(&adapter) {
+            return Ok((adapter, device, AdapterKind::Software));
+        }
+    }
+    Err(Error::NoSuitableAdapter)
+}
+
+pub(crate) fn adapter_kind(adapter: &IDXGIAdapter1) -> Result<AdapterKind> {
+    let mut adapter_desc = Default::default();
+    unsafe { adapter.GetDesc1(&mut adapter_desc) }?;
+
+    Ok(AdapterInfo::from(&adapter_desc).kind())
+}
+
+fn create_device(adapter: &IDXGIAdapter1) -> Option<ID3D12Device> {
+    let mut device = None;
+    unsafe { D3D12CreateDevice(adapter, D3D_FEATURE_LEVEL_11_0, &mut device) }.ok()?;
+    device
+}
+
+fn max_feature_level(device: &ID3D12Device) -> Option<D3D_FEATURE_LEVEL> {
+    let feature_levels = [
+        D3D_FEATURE_LEVEL_11_0,
+        D3D_FEATURE_LEVEL_11_1,
+        D3D_FEATURE_LEVEL_12_0,
+        D3D_FEATURE_LEVEL_12_1,
+        D3D_FEATURE_LEVEL_12_2,
+    ];
+    let mut data = D3D12_FEATURE_DATA_FEATURE_LEVELS {
+        NumFeatureLevels: feature_levels.len() as _,
+        pFeatureLevelsRequested: feature_levels.as_ptr(),
+        MaxSupportedFeatureLevel: D3D_FEATURE_LEVEL_11_0,
+    };
+    unsafe {
+        device.CheckFeatureSupport(
+            D3D12_FEATURE_FEATURE_LEVELS,
+            &mut data as *mut _ as _,
+            std::mem::size_of_val(&data) as _,
+        )
+    }
+    .ok()?;
+    Some(data.MaxSupportedFeatureLevel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adapter(description: &str, vendor_id: u32, luid: i32, software: bool) -> AdapterInfo {
+        AdapterInfo {
+            description: description.to_owned(),
+            vendor_id,
+            device_id: 0,
+            luid: LUID {
+                LowPart: 0,
+                HighPart: luid,
+            },
+            dedicated_video_memory: 0,
+            software,
+            max_feature_level: None,
+        }
+    }
+
+    fn adapters() -> Vec<AdapterInfo> {
+        vec![
+            adapter("Intel(R) UHD Graphics", 0x8086, 1, false),
+            adapter("NVIDIA GeForce RTX 4070", 0x10de, 2, false),
+            adapter("Microsoft Basic Render Driver", 0x1414, 3, true),
+        ]
+    }
+
+    #[test]
+    fn default_keeps_hardware_adapters_in_order() {
+        let adapters = adapters();
+
+        assert_eq!(AdapterPreference::Default.candidates(&adapters), [0, 1]);
+        assert_eq!(
+            AdapterPreference::MinimumPower.candidates(&adapters),
+            [0, 1]
+        );
+    }
+
+    #[test]
+    fn luid_selects_matching_adapter() {
+        let adapters = adapters();
+
+        let preference = AdapterPreference::adapter(&adapters[1]);
+        assert_eq!(preference.candidates(&adapters), [1]);
+
+        let preference = AdapterPreference::Luid(LUID {
+            LowPart: 0,
+            HighPart: 42,
+        });
+        assert!(preference.candidates(&adapters).is_empty());
+    }
+
+    #[test]
+    fn luid_selects_software_adapter() {
+        let adapters = adapters();
+
+        let preference = AdapterPreference::adapter(&adapters[2]);
+        assert_eq!(preference.candidates(&adapters), [2]);
+    }
+
+    #[test]
+    fn matching_filters_hardware_adapters() {
+        let adapters = adapters();
+
+        assert_eq!(AdapterPreference::vendor(0x10de).candidates(&adapters), [1]);
+        assert_eq!(
+            AdapterPreference::name_contains("intel").candidates(&adapters),
+            [0]
+        );
+        assert!(AdapterPreference::name_contains("basic render")
+            .candidates(&adapters)
+            .is_empty());
+    }
+
+    #[test]
+    fn custom_sees_every_adapter() {
+        let adapters = adapters();
+
+        let preference = AdapterPreference::Custom(Box::new(|adapters| {
+            adapters.iter().position(|info| info.software)
+        }));
+        assert_eq!(preference.candidates(&adapters), [2]);
+    }
+
+    #[test]
+    fn custom_ignores_out_of_range_index() {
+        let adapters = adapters();
+
+        let preference = AdapterPreference::Custom(Box::new(|adapters| Some(adapters.len())));
+        assert!(preference.candidates(&adapters).is_empty());
+
+        let preference = AdapterPreference::Custom(Box::new(|_| None));
+        assert!(preference.candidates(&adapters).is_empty());
+    }
+}
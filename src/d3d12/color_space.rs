@@ -0,0 +1,343 @@
+use skia_safe::{named_transfer_fn, ColorSpace, ColorSpaceTransferFn, Data};
+use windows::{
+    core::{PCWSTR, PWSTR},
+    Win32::{
+        Foundation::HWND,
+        Graphics::Gdi::{
+            CreateDCW, DeleteDC, GetMonitorInfoW, MonitorFromWindow, HMONITOR, MONITORINFO,
+            MONITORINFOEXW, MONITOR_DEFAULTTONEAREST,
+        },
+        UI::ColorSystem::GetICMProfileW,
+    },
+};
+
+use crate::error::{Error, Result};
+
+// Row-major RGB to XYZ D50 matrices, matching SkNamedGamut.
+#[rustfmt::skip]
+pub(crate) const DISPLAY_P3_GAMUT: [f32; 9] = [
+    0.515102, 0.291965, 0.157153,
+    0.241182, 0.692236, 0.0665819,
+    -0.00104941, 0.0418818, 0.784378,
+];
+#[rustfmt::skip]
+pub(crate) const REC2020_GAMUT: [f32; 9] = [
+    0.673459, 0.165661, 0.125100,
+    0.279033, 0.675338, 0.0456288,
+    -0.00193139, 0.0299794, 0.797162,
+];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum SurfaceColorSpace {
+    /// Draw without color management, as if the display were sRGB.
+    #[default]
+    Unmanaged,
+    Srgb,
+    DisplayP3,
+    Icc(Vec<u8>),
+    Custom(ColorSpace),
+    /// Follow the ICC profile of the monitor the window is on. Falls back to `Unmanaged` when
+    /// the monitor has no usable profile.
+    Monitor,
+}
+impl SurfaceColorSpace {
+    pub(crate) fn resolve(&self, hwnd: Option<HWND>) -> Result<Option<ColorSpace>> {
+        Ok(match self {
+            Self::Unmanaged => None,
+            Self::Srgb => Some(ColorSpace::new_srgb()),
+            Self::DisplayP3 => Some(new_rgb(&named_transfer_fn::SRGB, &DISPLAY_P3_GAMUT)),
+            Self::Icc(profile) => Some(from_icc(profile).ok_or(Error::InvalidIccProfile)?),
+            Self::Custom(color_space) => Some(color_space.clone()),
+            Self::Monitor => hwnd.and_then(|hwnd| monitor_color_space(window_monitor(hwnd))),
+        })
+    }
+}
+
+// skia-safe does not bind SkColorSpace::MakeRGB, but the serialized form of a color space
+// is only a version header followed by the transfer function and the gamut matrix.
+pub(crate) fn new_rgb(transfer_fn: &ColorSpaceTransferFn, to_xyz_d50: &[f32; 9]) -> ColorSpace {
+    let t = transfer_fn;
+
+    let mut bytes = vec![1, 0, 0, 0];
+    for value in [t.g, t.a, t.b, t.c, t.d, t.e, t.f].iter().chain(to_xyz_d50) {
+        bytes.extend_from_slice(&value.to_ne_bytes());
+    }
+    ColorSpace::deserialize(Data::new_copy(&bytes))
+}
+
+pub(crate) fn from_icc(profile: &[u8]) -> Option<ColorSpace> {
+    let (transfer_fn, to_xyz_d50) = parse_icc(profile)?;
+    Some(new_rgb(&transfer_fn, &to_xyz_d50))
+}
+
+// Reads matrix/TRC RGB profiles. SkColorSpace has a single transfer function, so the red
+// curve stands in for all three channels, and sampled curves are reduced to a pure gamma.
+pub(crate) fn parse_icc(profile: &[u8]) -> Option<(ColorSpaceTransferFn, [f32; 9])> {
+    let bytes = |offset: usize, length: usize| profile.get(offset..offset.checked_add(length)?);
+    let u16_at = |offset| Some(u16::from_be_bytes(bytes(offset, 2)?.try_into().ok()?));
+    let u32_at = |offset| Some(u32::from_be_bytes(bytes(offset, 4)?.try_into().ok()?));
+    let s15_fixed16_at = |offset| Some(u32_at(offset)? as i32 as f32 / 65536.0);
+
+    if bytes(16, 4)? != b"RGB " || bytes(36, 4)? != b"acsp" {
+        return None;
+    }
+
+    let tag_count = (u32_at(128)? as usize).min(profile.len().saturating_sub(132) / 12);
+    let tag = |signature: &[u8]| {
+        (0..tag_count).find_map(|i| {
+            let entry = 132 + i * 12;
+            if bytes(entry, 4)? == signature {
+                u32_at(entry + 4).map(|offset| offset as usize)
+            } else {
+                None
+            }
+        })
+    };
+    let xyz = |signature: &[u8]| {
+        let offset = tag(signature)?;
+        if bytes(offset, 4)? != b"XYZ " {
+            return None;
+        }
+        Some([
+            s15_fixed16_at(offset + 8)?,
+            s15_fixed16_at(offset + 12)?,
+            s15_fixed16_at(offset + 16)?,
+        ])
+    };
+
+    let [rx, ry, rz] = xyz(b"rXYZ")?;
+    let [gx, gy, gz] = xyz(b"gXYZ")?;
+    let [bx, by, bz] = xyz(b"bXYZ")?;
+    let determinant =
+        rx * (gy * bz - by * gz) - gx * (ry * bz - by * rz) + bx * (ry * gz - gy * rz);
+    if determinant.abs() < f32::EPSILON {
+        return None;
+    }
+    let to_xyz_d50 = [rx, gx, bx, ry, gy, by, rz, gz, bz];
+
+    let gamma = |g| ColorSpaceTransferFn {
+        g,
+        a: 1.0,
+        b: 0.0,
+        c: 0.0,
+        d: 0.0,
+        e: 0.0,
+        f: 0.0,
+    };
+    let offset = tag(b"rTRC")?;
+    let transfer_fn = match bytes(offset, 4)? {
+        b"curv" => match u32_at(offset + 8)? as usize {
+            0 => gamma(1.0),
+            1 => gamma(u16_at(offset + 12)? as f32 / 256.0),
+            count => {
+                let middle = count / 2;
+                let x = middle as f32 / (count - 1) as f32;
+                let y = u16_at(offset + 12 + middle * 2)? as f32 / 65535.0;
+                if y <= 0.0 || y >= 1.0 {
+                    return None;
+                }
+                gamma(y.ln() / x.ln())
+            }
+        },
+        b"para" => {
+            let parameter_count = match u16_at(offset + 8)? {
+                0 => 1,
+                1 => 3,
+                2 => 4,
+                3 => 5,
+                4 => 7,
+                _ => return None,
+            };
+            let mut p = [0.0; 7];
+            for (i, value) in p.iter_mut().take(parameter_count).enumerate() {
+                *value = s15_fixed16_at(offset + 12 + i * 4)?;
+            }
+            let [g, a, b, c, d, e, f] = p;
+
+            // ICC parametric curves are special cases of (ax + b)^g + e above d, cx + f below.
+            match parameter_count {
+                1 => gamma(g),
+                3 => ColorSpaceTransferFn {
+                    g,
+                    a,
+                    b,
+                    c: 0.0,
+                    d: -b / a,
+                    e: 0.0,
+                    f: 0.0,
+                },
+                4 => ColorSpaceTransferFn {
+                    g,
+                    a,
+                    b,
+                    c: 0.0,
+                    d: -b / a,
+                    e: c,
+                    f: c,
+                },
+                _ => ColorSpaceTransferFn {
+                    g,
+                    a,
+                    b,
+                    c,
+                    d,
+                    e,
+                    f,
+                },
+            }
+        }
+        _ => return None,
+    };
+    if !is_srgbish(&transfer_fn) {
+        return None;
+    }
+    Some((transfer_fn, to_xyz_d50))
+}
+
+// The conditions skcms needs to classify a curve as sRGB-ish. SkColorSpace::MakeRGB returns
+// null for anything else, and ColorSpace::deserialize unwraps that null.
+fn is_srgbish(t: &ColorSpaceTransferFn) -> bool {
+    [t.g, t.a, t.b, t.c, t.d, t.e, t.f]
+        .iter()
+        .all(|value| value.is_finite())
+        && t.g > 0.0
+        && t.a >= 0.0
+        && t.c >= 0.0
+        && t.d >= 0.0
+        && t.a * t.d + t.b >= 0.0
+}
+
+pub(crate) fn window_monitor(hwnd: HWND) -> HMONITOR {
+    unsafe { MonitorFromWindow(hwnd, MONITOR_DEFAULTTONEAREST) }
+}
+
+// Falls back to an unmanaged surface when the monitor has no usable profile.
+pub(crate) fn monitor_color_space(monitor: HMONITOR) -> Option<ColorSpace> {
+    let color_space = monitor_profile(monitor).as_deref().and_then(from_icc);
+    if color_space.is_none() {
+        log::warn!("no usable ICC profile for monitor {monitor:?}, drawing unmanaged");
+    }
+    color_space
+}
+
+fn monitor_profile(monitor: HMONITOR) -> Option<Vec<u8>> {
+    let mut info = MONITORINFOEXW::default();
+    info.monitorInfo.cbSize = std::mem::size_of::<MONITORINFOEXW>() as _;
+    unsafe { GetMonitorInfoW(monitor, &mut info as *mut _ as *mut MONITORINFO) }
+        .ok()
+        .ok()?;
+
+    let hdc = unsafe {
+        CreateDCW(
+            PCWSTR::null(),
+            PCWSTR(info.szDevice.as_ptr()),
+            PCWSTR::null(),
+            None,
+        )
+    };
+    if hdc.is_invalid() {
+        return None;
+    }
+    let mut path = [0u16; 260];
+    let mut length = path.len() as u32;
+    let found = unsafe { GetICMProfileW(hdc, &mut length, PWSTR(path.as_mut_ptr())) };
+    let _ = unsafe { DeleteDC(hdc) };
+    found.ok().ok()?;
+
+    let length = path.iter().position(|&c| c == 0).unwrap_or(path.len());
+    std::fs::read(String::from_utf16_lossy(&path[..length])).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s15_fixed16(value: f32) -> [u8; 4] {
+        ((value * 65536.0).round() as i32).to_be_bytes()
+    }
+
+    // A matrix/TRC profile with sRGB primaries and the given red curve.
+    fn profile(trc: &[u8]) -> Vec<u8> {
+        let xyz = |[x, y, z]: [f32; 3]| {
+            let mut tag = b"XYZ \0\0\0\0".to_vec();
+            for value in [x, y, z] {
+                tag.extend_from_slice(&s15_fixed16(value));
+            }
+            tag
+        };
+        let tags: [(&[u8], Vec<u8>); 4] = [
+            (b"rXYZ", xyz([0.4361, 0.2225, 0.0139])),
+            (b"gXYZ", xyz([0.3851, 0.7169, 0.0971])),
+            (b"bXYZ", xyz([0.1431, 0.0606, 0.7141])),
+            (b"rTRC", trc.to_vec()),
+        ];
+
+        let mut profile = vec![0; 128];
+        profile[16..20].copy_from_slice(b"RGB ");
+        profile[36..40].copy_from_slice(b"acsp");
+        profile.extend_from_slice(&(tags.len() as u32).to_be_bytes());
+
+        let mut offset = 132 + tags.len() * 12;
+        for (signature, data) in &tags {
+            profile.extend_from_slice(signature);
+            profile.extend_from_slice(&(offset as u32).to_be_bytes());
+            profile.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            offset += data.len();
+        }
+        for (_, data) in &tags {
+            profile.extend_from_slice(data);
+        }
+        profile
+    }
+
+    fn para(function_type: u16, parameters: &[f32]) -> Vec<u8> {
+        let mut tag = b"para\0\0\0\0".to_vec();
+        tag.extend_from_slice(&function_type.to_be_bytes());
+        tag.extend_from_slice(&[0, 0]);
+        for &parameter in parameters {
+            tag.extend_from_slice(&s15_fixed16(parameter));
+        }
+        tag
+    }
+
+    #[test]
+    fn parses_gamma_curve() {
+        let mut curv = b"curv\0\0\0\0".to_vec();
+        curv.extend_from_slice(&1u32.to_be_bytes());
+        curv.extend_from_slice(&((2.2 * 256.0) as u16).to_be_bytes());
+
+        let (transfer_fn, to_xyz_d50) = parse_icc(&profile(&curv)).unwrap();
+        assert!((transfer_fn.g - 2.2).abs() < 0.01);
+        assert!((to_xyz_d50[0] - 0.4361).abs() < 0.001);
+        assert!((to_xyz_d50[3] - 0.2225).abs() < 0.001);
+    }
+
+    #[test]
+    fn parses_srgb_parametric_curve() {
+        let srgb = para(3, &[2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045]);
+
+        let (transfer_fn, _) = parse_icc(&profile(&srgb)).unwrap();
+        assert!((transfer_fn.g - 2.4).abs() < 0.001);
+        assert!((transfer_fn.d - 0.04045).abs() < 0.001);
+    }
+
+    #[test]
+    fn rejects_curves_skcms_cannot_represent() {
+        // d = -b / a ends up negative.
+        assert!(parse_icc(&profile(&para(1, &[2.2, 1.0, 0.1]))).is_none());
+        // Negative linear segment slope and threshold.
+        assert!(parse_icc(&profile(&para(3, &[2.2, 1.0, 0.0, -0.1, -0.1]))).is_none());
+        // a = 0 makes d infinite.
+        assert!(parse_icc(&profile(&para(1, &[2.2, 0.0, 0.0]))).is_none());
+        assert!(parse_icc(&profile(&para(0, &[0.0]))).is_none());
+    }
+
+    #[test]
+    fn rejects_non_rgb_profiles() {
+        let mut profile = profile(&para(0, &[2.2]));
+        profile[16..20].copy_from_slice(b"GRAY");
+        assert!(parse_icc(&profile).is_none());
+
+        assert!(parse_icc(&[]).is_none());
+    }
+}
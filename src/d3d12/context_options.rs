@@ -0,0 +1,42 @@
+use skia_safe::gpu::{self, context_options::Enable, DirectContext};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContextOptions {
+    pub glyph_cache_texture_maximum_bytes: Option<usize>,
+    pub allow_multiple_glyph_cache_textures: Option<bool>,
+    pub disable_driver_correctness_workarounds: bool,
+    pub resource_cache_limit: Option<usize>,
+    // 0 disables the MSAA path renderers.
+    pub internal_multisample_count: Option<i32>,
+    pub runtime_program_cache_size: Option<i32>,
+    pub reduced_shader_variations: bool,
+}
+impl ContextOptions {
+    pub(crate) fn to_skia(&self) -> gpu::ContextOptions {
+        let mut options = gpu::ContextOptions::new();
+
+        if let Some(bytes) = self.glyph_cache_texture_maximum_bytes {
+            options.glyph_cache_texture_maximum_bytes = bytes;
+        }
+        if let Some(allow) = self.allow_multiple_glyph_cache_textures {
+            options.allow_multiple_glyph_cache_textures =
+                if allow { Enable::Yes } else { Enable::No };
+        }
+        options.disable_driver_correctness_workarounds =
+            self.disable_driver_correctness_workarounds;
+        if let Some(count) = self.internal_multisample_count {
+            options.internal_multisample_count = count;
+        }
+        if let Some(size) = self.runtime_program_cache_size {
+            options.runtime_program_cache_size = size;
+        }
+        options.reduced_shader_variations = self.reduced_shader_variations;
+
+        options
+    }
+    pub(crate) fn apply(&self, direct_context: &mut DirectContext) {
+        if let Some(limit) = self.resource_cache_limit {
+            direct_context.set_resource_cache_limit(limit);
+        }
+    }
+}
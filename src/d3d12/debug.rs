@@ -0,0 +1,118 @@
+use skia_safe::gpu::d3d::ID3D12Device;
+use windows::{
+    core::Interface,
+    Win32::Graphics::Direct3D12::{
+        D3D12GetDebugInterface, ID3D12Debug, ID3D12Debug1, ID3D12InfoQueue, D3D12_MESSAGE,
+        D3D12_MESSAGE_SEVERITY, D3D12_MESSAGE_SEVERITY_CORRUPTION, D3D12_MESSAGE_SEVERITY_ERROR,
+        D3D12_MESSAGE_SEVERITY_INFO, D3D12_MESSAGE_SEVERITY_MESSAGE,
+        D3D12_MESSAGE_SEVERITY_WARNING,
+    },
+};
+
+use crate::error::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageSeverity {
+    Corruption,
+    Error,
+    Warning,
+    Info,
+    Message,
+}
+impl MessageSeverity {
+    fn from_d3d12(severity: D3D12_MESSAGE_SEVERITY) -> Self {
+        match severity {
+            D3D12_MESSAGE_SEVERITY_CORRUPTION => Self::Corruption,
+            D3D12_MESSAGE_SEVERITY_ERROR => Self::Error,
+            D3D12_MESSAGE_SEVERITY_WARNING => Self::Warning,
+            D3D12_MESSAGE_SEVERITY_INFO => Self::Info,
+            _ => Self::Message,
+        }
+    }
+    fn to_d3d12(self) -> D3D12_MESSAGE_SEVERITY {
+        match self {
+            Self::Corruption => D3D12_MESSAGE_SEVERITY_CORRUPTION,
+            Self::Error => D3D12_MESSAGE_SEVERITY_ERROR,
+            Self::Warning => D3D12_MESSAGE_SEVERITY_WARNING,
+            Self::Info => D3D12_MESSAGE_SEVERITY_INFO,
+            Self::Message => D3D12_MESSAGE_SEVERITY_MESSAGE,
+        }
+    }
+    fn log_level(self) -> log::Level {
+        match self {
+            Self::Corruption | Self::Error => log::Level::Error,
+            Self::Warning => log::Level::Warn,
+            Self::Info => log::Level::Info,
+            Self::Message => log::Level::Debug,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DebugOptions {
+    pub gpu_based_validation: bool,
+    pub break_on_severity: Vec<MessageSeverity>,
+}
+
+pub(crate) fn enable_debug_layer(options: &DebugOptions) -> Result<()> {
+    let mut debug: Option<ID3D12Debug> = None;
+    unsafe { D3D12GetDebugInterface(&mut debug) }?;
+
+    if let Some(debug) = debug {
+        unsafe { debug.EnableDebugLayer() };
+
+        if options.gpu_based_validation {
+            let debug1: ID3D12Debug1 = debug.cast()?;
+            unsafe { debug1.SetEnableGPUBasedValidation(true) };
+        }
+    }
+    Ok(())
+}
+
+pub(crate) struct InfoQueue(ID3D12InfoQueue);
+impl InfoQueue {
+    pub(crate) fn new(device: &ID3D12Device, options: &DebugOptions) -> Result<Self> {
+        let info_queue = Self(device.cast()?);
+        for &severity in &options.break_on_severity {
+            info_queue.set_break_on_severity(severity, true)?;
+        }
+        Ok(info_queue)
+    }
+    pub(crate) fn set_break_on_severity(
+        &self,
+        severity: MessageSeverity,
+        enable: bool,
+    ) -> Result<()> {
+        Ok(unsafe { self.0.SetBreakOnSeverity(severity.to_d3d12(), enable) }?)
+    }
+    pub(crate) fn drain(&self) {
+        let count = unsafe { self.0.GetNumStoredMessages() };
+        for i in 0..count {
+            let mut length = 0;
+            if unsafe { self.0.GetMessage(i, None, &mut length) }.is_err() {
+                continue;
+            }
+
+            // D3D12_MESSAGE is followed by its description in the same allocation.
+            let mut buffer = vec![0u64; length.div_ceil(std::mem::size_of::<u64>())];
+            let message = buffer.as_mut_ptr() as *mut D3D12_MESSAGE;
+            if unsafe { self.0.GetMessage(i, Some(message), &mut length) }.is_err() {
+                continue;
+            }
+            let message = unsafe { &*message };
+
+            let description = unsafe {
+                std::slice::from_raw_parts(message.pDescription, message.DescriptionByteLength)
+            };
+            let description = String::from_utf8_lossy(description);
+
+            log::log!(
+                target: "d3d12",
+                MessageSeverity::from_d3d12(message.Severity).log_level(),
+                "{}",
+                description.trim_end_matches('\0')
+            );
+        }
+        unsafe { self.0.ClearStoredMessages() };
+    }
+}
@@ -0,0 +1,231 @@
+use std::ffi::CStr;
+
+use skia_safe::gpu::d3d::ID3D12Device;
+use windows::{
+    core::{Interface, HRESULT, PCWSTR},
+    Win32::Graphics::Direct3D12::{
+        D3D12GetDebugInterface, ID3D12DeviceRemovedExtendedData,
+        ID3D12DeviceRemovedExtendedDataSettings, D3D12_AUTO_BREADCRUMB_NODE,
+        D3D12_DRED_ALLOCATION_NODE, D3D12_DRED_ENABLEMENT_FORCED_ON,
+    },
+};
+
+use crate::error::Result;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DeviceRemovedReport {
+    pub reason_code: i32,
+    pub reason: String,
+    pub breadcrumbs: Vec<Breadcrumbs>,
+    pub page_fault: Option<PageFault>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Breadcrumbs {
+    pub command_list_name: Option<String>,
+    pub command_queue_name: Option<String>,
+    pub completed_operations: u32,
+    pub operations: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PageFault {
+    pub virtual_address: u64,
+    pub existing_allocations: Vec<Allocation>,
+    pub recently_freed_allocations: Vec<Allocation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Allocation {
+    pub name: Option<String>,
+    pub allocation_type: &'static str,
+}
+
+pub(crate) fn enable() -> Result<()> {
+    let mut settings: Option<ID3D12DeviceRemovedExtendedDataSettings> = None;
+    unsafe { D3D12GetDebugInterface(&mut settings) }?;
+
+    if let Some(settings) = settings {
+        unsafe {
+            settings.SetAutoBreadcrumbsEnablement(D3D12_DRED_ENABLEMENT_FORCED_ON);
+            settings.SetPageFaultEnablement(D3D12_DRED_ENABLEMENT_FORCED_ON);
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn report(device: &ID3D12Device, reason: HRESULT) -> DeviceRemovedReport {
+    let dred = device.cast::<ID3D12DeviceRemovedExtendedData>().ok();
+
+    let breadcrumbs = dred
+        .as_ref()
+        .and_then(|dred| unsafe { dred.GetAutoBreadcrumbsOutput() }.ok())
+        .map(|output| unsafe { collect_breadcrumbs(output.pHeadAutoBreadcrumbNode) })
+        .unwrap_or_default();
+
+    let page_fault = dred
+        .as_ref()
+        .and_then(|dred| unsafe { dred.GetPageFaultAllocationOutput() }.ok())
+        .map(|output| unsafe {
+            PageFault {
+                virtual_address: output.PageFaultVA,
+                existing_allocations: collect_allocations(output.pHeadExistingAllocationNode),
+                recently_freed_allocations: collect_allocations(
+                    output.pHeadRecentFreedAllocationNode,
+                ),
+            }
+        });
+
+    DeviceRemovedReport {
+        reason_code: reason.0,
+        reason: reason.message(),
+        breadcrumbs,
+        page_fault,
+    }
+}
+
+unsafe fn collect_breadcrumbs(mut node: *const D3D12_AUTO_BREADCRUMB_NODE) -> Vec<Breadcrumbs> {
+    let mut breadcrumbs = Vec::new();
+    while let Some(current) = node.as_ref() {
+        let operations = if current.pCommandHistory.is_null() {
+            &[]
+        } else {
+            std::slice::from_raw_parts(current.pCommandHistory, current.BreadcrumbCount as _)
+        };
+
+        breadcrumbs.push(Breadcrumbs {
+            command_list_name: debug_name(
+                current.pCommandListDebugNameW,
+                current.pCommandListDebugNameA,
+            ),
+            command_queue_name: debug_name(
+                current.pCommandQueueDebugNameW,
+                current.pCommandQueueDebugNameA,
+            ),
+            completed_operations: current.pLastBreadcrumbValue.as_ref().copied().unwrap_or(0),
+            operations: operations
+                .iter()
+                .map(|op| {
+                    usize::try_from(op.0)
+                        .ok()
+                        .and_then(|i| BREADCRUMB_OPS.get(i))
+                        .copied()
+                        .unwrap_or("UNKNOWN")
+                })
+                .collect(),
+        });
+        node = current.pNext;
+    }
+    breadcrumbs
+}
+
+unsafe fn collect_allocations(mut node: *const D3D12_DRED_ALLOCATION_NODE) -> Vec<Allocation> {
+    let mut allocations = Vec::new();
+    while let Some(current) = node.as_ref() {
+        allocations.push(Allocation {
+            name: debug_name(current.ObjectNameW, current.ObjectNameA),
+            allocation_type: ALLOCATION_TYPES
+                .iter()
+                .find(|(value, _)| *value == current.AllocationType.0)
+                .map(|(_, name)| *name)
+                .unwrap_or("UNKNOWN"),
+        });
+        node = current.pNext;
+    }
+    allocations
+}
+
+unsafe fn debug_name(wide: PCWSTR, narrow: *const u8) -> Option<String> {
+    if !wide.is_null() {
+        wide.to_string().ok()
+    } else if !narrow.is_null() {
+        Some(CStr::from_ptr(narrow as _).to_string_lossy().into_owned())
+    } else {
+        None
+    }
+}
+
+const BREADCRUMB_OPS: &[&str] = &[
+    "SETMARKER",
+    "BEGINEVENT",
+    "ENDEVENT",
+    "DRAWINSTANCED",
+    "DRAWINDEXEDINSTANCED",
+    "EXECUTEINDIRECT",
+    "DISPATCH",
+    "COPYBUFFERREGION",
+    "COPYTEXTUREREGION",
+    "COPYRESOURCE",
+    "COPYTILES",
+    "RESOLVESUBRESOURCE",
+    "CLEARRENDERTARGETVIEW",
+    "CLEARUNORDEREDACCESSVIEW",
+    "CLEARDEPTHSTENCILVIEW",
+    "RESOURCEBARRIER",
+    "EXECUTEBUNDLE",
+    "PRESENT",
+    "RESOLVEQUERYDATA",
+    "BEGINSUBMISSION",
+    "ENDSUBMISSION",
+    "DECODEFRAME",
+    "PROCESSFRAMES",
+    "ATOMICCOPYBUFFERUINT",
+    "ATOMICCOPYBUFFERUINT64",
+    "RESOLVESUBRESOURCEREGION",
+    "WRITEBUFFERIMMEDIATE",
+    "DECODEFRAME1",
+    "SETPROTECTEDRESOURCESESSION",
+    "DECODEFRAME2",
+    "PROCESSFRAMES1",
+    "BUILDRAYTRACINGACCELERATIONSTRUCTURE",
+    "EMITRAYTRACINGACCELERATIONSTRUCTUREPOSTBUILDINFO",
+    "COPYRAYTRACINGACCELERATIONSTRUCTURE",
+    "DISPATCHRAYS",
+    "INITIALIZEMETACOMMAND",
+    "EXECUTEMETACOMMAND",
+    "ESTIMATEMOTION",
+    "RESOLVEMOTIONVECTORHEAP",
+    "SETPIPELINESTATE1",
+    "INITIALIZEEXTENSIONCOMMAND",
+    "EXECUTEEXTENSIONCOMMAND",
+    "DISPATCHMESH",
+    "ENCODEFRAME",
+    "RESOLVEENCODEROUTPUTMETADATA",
+    "BARRIER",
+    "BEGIN_COMMAND_LIST",
+];
+
+const ALLOCATION_TYPES: &[(i32, &str)] = &[
+    (19, "COMMAND_QUEUE"),
+    (20, "COMMAND_ALLOCATOR"),
+    (21, "PIPELINE_STATE"),
+    (22, "COMMAND_LIST"),
+    (23, "FENCE"),
+    (24, "DESCRIPTOR_HEAP"),
+    (25, "HEAP"),
+    (27, "QUERY_HEAP"),
+    (28, "COMMAND_SIGNATURE"),
+    (29, "PIPELINE_LIBRARY"),
+    (30, "VIDEO_DECODER"),
+    (32, "VIDEO_PROCESSOR"),
+    (34, "RESOURCE"),
+    (35, "PASS"),
+    (36, "CRYPTOSESSION"),
+    (37, "CRYPTOSESSIONPOLICY"),
+    (38, "PROTECTEDRESOURCESESSION"),
+    (39, "VIDEO_DECODER_HEAP"),
+    (40, "COMMAND_POOL"),
+    (41, "COMMAND_RECORDER"),
+    (42, "STATE_OBJECT"),
+    (43, "METACOMMAND"),
+    (44, "SCHEDULINGGROUP"),
+    (45, "VIDEO_MOTION_ESTIMATOR"),
+    (46, "VIDEO_MOTION_VECTOR_HEAP"),
+    (47, "VIDEO_EXTENSION_COMMAND"),
+    (48, "VIDEO_ENCODER"),
+    (49, "VIDEO_ENCODER_HEAP"),
+];
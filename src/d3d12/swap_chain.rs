@@ -1,81 +1,230 @@
-use skia_safe::{gpu::BackendRenderTarget, Canvas, Surface};
+pub(crate) mod damage;
+pub(crate) mod dirty_rects;
+pub(crate) mod hdr;
+pub(crate) mod options;
+pub(crate) mod recording;
+
+use std::time::Duration;
+
+use damage::{Damage, DamageHistory, Frame};
+use dirty_rects::{clip_rects, PresentParameters, ScrollRect};
+use options::{PresentMode, SwapChainOptions};
+use recording::{FrameRecorder, FrameRegions};
+use skia_safe::{gpu::BackendRenderTarget, Canvas, ColorSpace, IRect, Region, Surface};
 use windows::Win32::{
-    Foundation::HWND,
-    Graphics::Dxgi::{
-        Common::{
-            DXGI_ALPHA_MODE, DXGI_ALPHA_MODE_UNSPECIFIED, DXGI_FORMAT_R8G8B8A8_UNORM,
-            DXGI_FORMAT_UNKNOWN, DXGI_SAMPLE_DESC,
+    Foundation::{CloseHandle, DXGI_STATUS_OCCLUDED, HANDLE, HWND, WAIT_OBJECT_0, WAIT_TIMEOUT},
+    Graphics::{
+        Dxgi::{
+            Common::DXGI_FORMAT_UNKNOWN, IDXGISwapChain3, DXGI_ERROR_WAS_STILL_DRAWING,
+            DXGI_PRESENT_DO_NOT_WAIT, DXGI_PRESENT_TEST, DXGI_SWAP_CHAIN_DESC1,
+            DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL,
         },
-        IDXGISwapChain3, DXGI_SWAP_CHAIN_DESC1, DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL,
-        DXGI_USAGE_RENDER_TARGET_OUTPUT,
+        Gdi::HMONITOR,
     },
+    System::Threading::WaitForSingleObjectEx,
 };
 
-use crate::d3d12::Backend;
+use crate::{
+    d3d12::{color_space, Backend},
+    error::{Error, Result},
+};
 
-pub(crate) const BUFFER_COUNT: u32 = 2;
+pub(crate) type SwapChainSurfaces = Vec<(Surface, BackendRenderTarget)>;
 
-pub(crate) type SwapChainSurfaceArray = [(Surface, BackendRenderTarget); BUFFER_COUNT as _];
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentOutcome {
+    Presented,
+    /// The window is minimized or covered. Nothing is drawn until a present test shows it
+    /// again, so the app can stop rendering until `is_occluded` returns false.
+    Occluded,
+    /// The present queue was full and the swap chain was created with `do_not_wait`. The
+    /// frame was not presented.
+    StillDrawing,
+    /// `draw_recorded` or `draw_recorded_regions` recorded the same frame as the one on
+    /// screen, or every dirty rect was outside the buffer, so nothing was drawn or presented.
+    Unchanged,
+    /// There is no swap chain to present to until it is recreated.
+    Skipped,
+}
 
 pub struct HwndSwapChain {
     hwnd: HWND,
+    monitor: HMONITOR,
+    options: SwapChainOptions,
     swap_chain: SwapChainState,
 }
 impl HwndSwapChain {
-    pub(crate) fn new(hwnd: HWND, swap_chain: SwapChain) -> Self {
+    pub(crate) fn new(hwnd: HWND, options: SwapChainOptions, swap_chain: SwapChain) -> Self {
         Self {
             hwnd,
+            monitor: color_space::window_monitor(hwnd),
+            options,
             swap_chain: SwapChainState::Active(swap_chain),
         }
     }
     pub fn resize(&mut self, env: &mut Backend, width: u32, height: u32) {
         self.swap_chain.resize(env, width, height);
     }
-    pub fn draw(&mut self, env: &mut Backend, f: impl FnMut(&Canvas)) -> windows::core::Result<()> {
+    pub fn draw(&mut self, env: &mut Backend, f: impl FnMut(&Canvas)) -> Result<PresentOutcome> {
+        self.draw_dirty(env, &[], None, f)
+    }
+    pub fn draw_dirty(
+        &mut self,
+        env: &mut Backend,
+        dirty_rects: &[IRect],
+        scroll: Option<&ScrollRect>,
+        mut f: impl FnMut(&Canvas),
+    ) -> Result<PresentOutcome> {
         self.recreate_if_needed(env)?;
 
-        unsafe { self.swap_chain.get_active_mut().unwrap_unchecked() }
-            .draw(env, f)
-            .ok()
+        match self.swap_chain.draw_dirty(env, dirty_rects, scroll, &mut f) {
+            // A new swap chain has no previous frame to keep, so redraw all of it.
+            Err(error) if error.is_device_removed() => {
+                self.recreate_if_needed(env)?;
+
+                self.swap_chain.draw_dirty(env, &[], None, f)
+            }
+            result => result,
+        }
+    }
+    pub fn draw_recorded(
+        &mut self,
+        env: &mut Backend,
+        mut f: impl FnMut(&Canvas),
+    ) -> Result<PresentOutcome> {
+        self.draw_recorded_regions(env, |regions| regions.whole(&mut f))
     }
-    pub fn get_surface(&mut self, env: &mut Backend) -> windows::core::Result<&mut Surface> {
+    pub fn draw_recorded_regions(
+        &mut self,
+        env: &mut Backend,
+        mut f: impl FnMut(&mut FrameRegions),
+    ) -> Result<PresentOutcome> {
         self.recreate_if_needed(env)?;
 
-        Ok(self.swap_chain.get_active_mut().unwrap().get_surface())
-    }
-    pub fn present(&mut self, env: &mut Backend) {
-        if let Some(swap_chain) = self.swap_chain.get_active_mut() {
-            swap_chain.present(env);
+        match self.swap_chain.draw_recorded_regions(env, &mut f) {
+            Err(error) if error.is_device_removed() => {
+                self.recreate_if_needed(env)?;
+
+                self.swap_chain.draw_recorded_regions(env, f)
+            }
+            result => result,
         }
     }
-    fn recreate_if_needed(&mut self, env: &mut Backend) -> windows::core::Result<()> {
-        if let Some((width, height)) = self.swap_chain.needs_resize() {
-            env.recreate_context_if_needed()?;
+    pub fn frame(&mut self, env: &mut Backend) -> Result<Frame> {
+        self.recreate_if_needed(env)?;
+
+        Ok(self.swap_chain.frame())
+    }
+    pub fn get_surface(&mut self, env: &mut Backend) -> Result<&mut Surface> {
+        self.recreate_if_needed(env)?;
 
-            self.swap_chain =
-                SwapChainState::Active(env.create_swap_chain_for_hwnd(self.hwnd, width, height)?);
+        self.swap_chain
+            .get_active_mut()
+            .ok_or(Error::ContextLost)?
+            .get_surface()
+    }
+    pub fn present(&mut self, env: &mut Backend) -> Result<PresentOutcome> {
+        self.swap_chain.present(env)
+    }
+    /// Checks whether the window became visible again without presenting anything.
+    pub fn is_occluded(&mut self, env: &mut Backend) -> Result<bool> {
+        self.swap_chain.is_occluded(env)
+    }
+    pub fn present_mode(&self) -> PresentMode {
+        self.swap_chain.present_mode(&self.options)
+    }
+    pub fn wait_for_next_frame(&self, timeout: Option<Duration>) -> Result<bool> {
+        self.swap_chain.wait_for_next_frame(timeout)
+    }
+    /// Returns the mode actually in use, see [`SwapChain::set_present_mode`].
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) -> Result<PresentMode> {
+        self.swap_chain
+            .set_present_mode(&mut self.options, present_mode)
+    }
+    fn recreate_if_needed(&mut self, env: &mut Backend) -> Result<()> {
+        env.recreate_context_if_needed()?;
+        self.swap_chain.invalidate_if_stale(env);
+
+        if let Some((width, height)) = self.swap_chain.needs_resize() {
+            self.monitor = color_space::window_monitor(self.hwnd);
+            self.swap_chain = SwapChainState::Active(env.create_swap_chain_for_hwnd(
+                self.hwnd,
+                width,
+                height,
+                &self.options,
+            )?);
+        } else {
+            self.update_monitor_color_space(env)?;
         }
         Ok(())
     }
+    // Re-wraps the buffers when the window has moved to a monitor with a different profile.
+    fn update_monitor_color_space(&mut self, env: &mut Backend) -> Result<()> {
+        if !self.options.tracks_monitor() {
+            return Ok(());
+        }
+        let monitor = color_space::window_monitor(self.hwnd);
+        if monitor == self.monitor {
+            return Ok(());
+        }
+        self.monitor = monitor;
+
+        match self.swap_chain.get_active_mut() {
+            Some(swap_chain) => {
+                swap_chain.set_color_space(env, color_space::monitor_color_space(monitor))
+            }
+            None => Ok(()),
+        }
+    }
 }
 
 pub(crate) enum SwapChainState {
     Active(SwapChain),
+    // Minimized or covered. The swap chain is kept, but frames are skipped.
+    Occluded(SwapChain),
     Resizing { new_width: u32, new_height: u32 },
 }
 impl SwapChainState {
     pub(crate) fn get_active(&self) -> Option<&SwapChain> {
         match self {
-            Self::Active(swap_chain) => Some(swap_chain),
+            Self::Active(swap_chain) | Self::Occluded(swap_chain) => Some(swap_chain),
             _ => None,
         }
     }
     pub(crate) fn get_active_mut(&mut self) -> Option<&mut SwapChain> {
         match self {
-            Self::Active(swap_chain) => Some(swap_chain),
+            Self::Active(swap_chain) | Self::Occluded(swap_chain) => Some(swap_chain),
             _ => None,
         }
     }
+    fn set_occluded(&mut self, occluded: bool) {
+        let state = std::mem::replace(
+            self,
+            Self::Resizing {
+                new_width: 0,
+                new_height: 0,
+            },
+        );
+        *self = match state {
+            Self::Active(swap_chain) | Self::Occluded(swap_chain) if occluded => {
+                Self::Occluded(swap_chain)
+            }
+            Self::Active(swap_chain) | Self::Occluded(swap_chain) => Self::Active(swap_chain),
+            state => state,
+        }
+    }
+    // Only an occluded swap chain is probed, with a test present that shows nothing.
+    pub(crate) fn is_occluded(&mut self, env: &mut Backend) -> Result<bool> {
+        self.invalidate_if_stale(env);
+
+        let result = match self {
+            Self::Occluded(swap_chain) => swap_chain.is_occluded(env),
+            _ => return Ok(false),
+        };
+        let occluded = self.handle_device_lost(env, result)?;
+        self.set_occluded(occluded);
+        Ok(occluded)
+    }
     pub(crate) fn needs_resize(&self) -> Option<(u32, u32)> {
         match self {
             Self::Resizing {
@@ -85,15 +234,135 @@ impl SwapChainState {
             _ => None,
         }
     }
+    pub(crate) fn frame(&self) -> Frame {
+        self.get_active()
+            .map(SwapChain::frame)
+            .unwrap_or_else(Frame::undefined)
+    }
+    // A swap chain that is waiting to be recreated never blocks.
+    pub(crate) fn wait_for_next_frame(&self, timeout: Option<Duration>) -> Result<bool> {
+        match self.get_active() {
+            Some(swap_chain) => swap_chain.wait_for_next_frame(timeout),
+            None => Ok(true),
+        }
+    }
+    pub(crate) fn present_mode(&self, options: &SwapChainOptions) -> PresentMode {
+        self.get_active()
+            .map(SwapChain::present_mode)
+            .unwrap_or(options.present_mode)
+    }
+    // Stores the mode in effect, so a recreated swap chain presents the same way rather than
+    // picking up a tearing request the old one could not honor.
+    pub(crate) fn set_present_mode(
+        &mut self,
+        options: &mut SwapChainOptions,
+        present_mode: PresentMode,
+    ) -> Result<PresentMode> {
+        let present_mode = match self.get_active_mut() {
+            Some(swap_chain) => swap_chain.set_present_mode(present_mode)?,
+            None => {
+                present_mode.validate()?;
+                present_mode
+            }
+        };
+        options.present_mode = present_mode;
+        Ok(present_mode)
+    }
+    pub(crate) fn invalidate_if_stale(&mut self, env: &Backend) {
+        if let Some(swap_chain) = self.get_active() {
+            if !env.is_current_context(swap_chain.generation) {
+                *self = Self::Resizing {
+                    new_width: swap_chain.width(),
+                    new_height: swap_chain.height(),
+                }
+            }
+        }
+    }
+    pub(crate) fn draw(
+        &mut self,
+        env: &mut Backend,
+        f: impl FnMut(&Canvas),
+    ) -> Result<PresentOutcome> {
+        self.draw_dirty(env, &[], None, f)
+    }
+    pub(crate) fn draw_dirty(
+        &mut self,
+        env: &mut Backend,
+        dirty_rects: &[IRect],
+        scroll: Option<&ScrollRect>,
+        f: impl FnMut(&Canvas),
+    ) -> Result<PresentOutcome> {
+        if self.is_occluded(env)? {
+            return Ok(PresentOutcome::Occluded);
+        }
+        let result = match self.get_active_mut() {
+            Some(swap_chain) => swap_chain.draw_dirty(env, dirty_rects, scroll, f),
+            None => return Ok(PresentOutcome::Skipped),
+        };
+        self.handle_outcome(env, result)
+    }
+    pub(crate) fn draw_recorded_regions(
+        &mut self,
+        env: &mut Backend,
+        f: impl FnOnce(&mut FrameRegions),
+    ) -> Result<PresentOutcome> {
+        if self.is_occluded(env)? {
+            return Ok(PresentOutcome::Occluded);
+        }
+        let result = match self.get_active_mut() {
+            Some(swap_chain) => swap_chain.draw_recorded_regions(env, f),
+            None => return Ok(PresentOutcome::Skipped),
+        };
+        self.handle_outcome(env, result)
+    }
+    pub(crate) fn present(&mut self, env: &mut Backend) -> Result<PresentOutcome> {
+        if self.is_occluded(env)? {
+            return Ok(PresentOutcome::Occluded);
+        }
+        let result = match self.get_active_mut() {
+            Some(swap_chain) => swap_chain.present(env),
+            None => return Ok(PresentOutcome::Skipped),
+        };
+        self.handle_outcome(env, result)
+    }
+    fn handle_outcome(
+        &mut self,
+        env: &mut Backend,
+        result: Result<PresentOutcome>,
+    ) -> Result<PresentOutcome> {
+        let outcome = self.handle_device_lost(env, result)?;
+        if outcome == PresentOutcome::Occluded {
+            self.set_occluded(true);
+        }
+        Ok(outcome)
+    }
+    fn handle_device_lost<T>(&mut self, env: &mut Backend, result: Result<T>) -> Result<T> {
+        if let Err(error) = &result {
+            if error.is_device_removed() {
+                env.device_lost(error.clone());
+                self.invalidate_if_stale(env);
+            }
+        }
+        result
+    }
+    // Minimized windows report a zero size. Their buffers are kept as they are and the swap
+    // chain is treated as occluded until a real size comes in.
     pub(crate) fn resize(&mut self, env: &mut Backend, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            self.set_occluded(true);
+            return;
+        }
         let needs_resize = self
             .get_active_mut()
-            .map(|swap_chain| {
-                if swap_chain.resize(env, width, height).is_err() {
+            .map(|swap_chain| match swap_chain.resize(env, width, height) {
+                Ok(()) => false,
+                Err(error) if error.is_device_removed() => {
+                    env.device_lost(error);
+                    true
+                }
+                Err(_) => {
                     env.release_context();
                     true
-                } else {
-                    false
                 }
             })
             .unwrap_or(true);
@@ -103,28 +372,64 @@ impl SwapChainState {
                 new_width: width,
                 new_height: height,
             }
+        } else {
+            self.set_occluded(false);
         }
     }
 }
 
 pub struct SwapChain {
     pub(crate) swap_chain: IDXGISwapChain3,
-    surfaces: Option<SwapChainSurfaceArray>,
+    surfaces: Option<SwapChainSurfaces>,
+    desc: DXGI_SWAP_CHAIN_DESC1,
+    color_space: Option<ColorSpace>,
+    present_mode: PresentMode,
+    do_not_wait: bool,
+    frame_latency_waitable: Option<FrameLatencyWaitable>,
+    damage_history: DamageHistory,
+    recorder: FrameRecorder,
+    generation: u64,
 }
 
 impl SwapChain {
-    pub(crate) fn new(swap_chain: IDXGISwapChain3, surfaces: SwapChainSurfaceArray) -> Self {
-        Self {
+    pub(crate) fn new(
+        swap_chain: IDXGISwapChain3,
+        surfaces: SwapChainSurfaces,
+        desc: DXGI_SWAP_CHAIN_DESC1,
+        color_space: Option<ColorSpace>,
+        present_mode: PresentMode,
+        options: &SwapChainOptions,
+        generation: u64,
+    ) -> Result<Self> {
+        let frame_latency_waitable = options
+            .max_frame_latency
+            .map(|max_frame_latency| FrameLatencyWaitable::new(&swap_chain, max_frame_latency))
+            .transpose()?;
+        let damage_history = DamageHistory::new(
+            desc.BufferCount,
+            desc.SwapEffect == DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL,
+        );
+
+        Ok(Self {
             swap_chain,
             surfaces: Some(surfaces),
-        }
+            desc,
+            color_space,
+            present_mode,
+            do_not_wait: options.do_not_wait,
+            frame_latency_waitable,
+            damage_history,
+            recorder: FrameRecorder::default(),
+            generation,
+        })
     }
-    pub fn resize(
-        &mut self,
-        env: &mut Backend,
-        width: u32,
-        height: u32,
-    ) -> windows::core::Result<()> {
+    pub fn width(&self) -> u32 {
+        self.desc.Width
+    }
+    pub fn height(&self) -> u32 {
+        self.desc.Height
+    }
+    pub fn resize(&mut self, env: &mut Backend, width: u32, height: u32) -> Result<()> {
         if width == 0 || height == 0 {
             return Ok(());
         }
@@ -133,61 +438,276 @@ impl SwapChain {
         self.surfaces = None;
 
         unsafe {
-            self.swap_chain
-                .ResizeBuffers(BUFFER_COUNT, width, height, DXGI_FORMAT_UNKNOWN, 0)
+            self.swap_chain.ResizeBuffers(
+                self.desc.BufferCount,
+                width,
+                height,
+                DXGI_FORMAT_UNKNOWN,
+                self.desc.Flags,
+            )
         }?;
+        self.desc.Width = width;
+        self.desc.Height = height;
+        self.damage_history.reset();
+        self.recorder.reset();
 
-        self.surfaces
-            .replace(env.create_swap_chain_surfaces(&self.swap_chain, width, height));
+        self.wrap_surfaces(env)
+    }
+    pub fn frame(&self) -> Frame {
+        let index = unsafe { self.swap_chain.GetCurrentBackBufferIndex() };
+        self.damage_history.frame(index)
+    }
+    pub fn present_mode(&self) -> PresentMode {
+        self.present_mode
+    }
+    /// Returns the mode actually in use. Tearing can only be turned on for swap chains that
+    /// were created with it, and falls back to `Immediate` otherwise. A `Vsync` interval
+    /// outside 1 to 4 is rejected, as in [`SwapChainOptions::validate`].
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) -> Result<PresentMode> {
+        present_mode.validate()?;
+
+        self.present_mode = match present_mode {
+            PresentMode::Tearing if present_mode.swap_chain_flags() & !self.desc.Flags != 0 => {
+                log::warn!(
+                    "swap chain was created without tearing support, presenting immediately"
+                );
+                PresentMode::Immediate
+            }
+            present_mode => present_mode,
+        };
+        Ok(self.present_mode)
+    }
+    /// Blocks until the swap chain can accept another frame. Returns false on timeout, and
+    /// true right away for swap chains created without a maximum frame latency.
+    pub fn wait_for_next_frame(&self, timeout: Option<Duration>) -> Result<bool> {
+        match &self.frame_latency_waitable {
+            Some(waitable) => waitable.wait(timeout),
+            None => Ok(true),
+        }
+    }
+    pub fn color_space(&self) -> Option<&ColorSpace> {
+        self.color_space.as_ref()
+    }
+    pub fn set_color_space(
+        &mut self,
+        env: &mut Backend,
+        color_space: Option<ColorSpace>,
+    ) -> Result<()> {
+        if self.color_space == color_space {
+            return Ok(());
+        }
+        env.cleanup();
+
+        self.surfaces = None;
+        self.color_space = color_space;
+        // Everything on screen was drawn for the old color space.
+        self.damage_history.reset();
+        self.recorder.reset();
+
+        self.wrap_surfaces(env)
+    }
+    fn wrap_surfaces(&mut self, env: &mut Backend) -> Result<()> {
+        self.surfaces.replace(env.create_swap_chain_surfaces(
+            &self.swap_chain,
+            &self.desc,
+            self.color_space.clone(),
+        )?);
         Ok(())
     }
-    pub fn draw(
+    pub fn draw(&mut self, env: &mut Backend, f: impl FnMut(&Canvas)) -> Result<PresentOutcome> {
+        self.draw_dirty(env, &[], None, f)
+    }
+    /// Only the dirty rects are handed to DWM. Drawing is clipped to them plus whatever
+    /// changed since the back buffer was last shown, so `f` has to draw the whole frame. No
+    /// rects means the whole buffer, and rects entirely outside the buffer skip the frame
+    /// with `PresentOutcome::Unchanged`. `FlipDiscard` swap chains do not keep their
+    /// buffers, so they ignore the rects and scroll and redraw fully.
+    pub fn draw_dirty(
         &mut self,
         env: &mut Backend,
-        mut f: impl FnMut(&Canvas),
-    ) -> windows::core::HRESULT {
-        let index = unsafe { self.swap_chain.GetCurrentBackBufferIndex() };
-        let surface = &mut self.surfaces.as_mut().unwrap()[index as usize].0;
+        dirty_rects: &[IRect],
+        scroll: Option<&ScrollRect>,
+        f: impl FnOnce(&Canvas),
+    ) -> Result<PresentOutcome> {
+        let Some((dirty_rects, scroll)) = self.partial_presentation(dirty_rects, scroll) else {
+            return Ok(PresentOutcome::Unchanged);
+        };
+        // The back buffer may be several frames old, so it also needs what changed since.
+        // Scrolled content is not moved inside it, so scrolling redraws everything.
+        let repaint = match scroll {
+            Some(_) => Damage::Full,
+            None => self.frame().repaint(&dirty_rects),
+        };
+        self.draw_clipped(&repaint, f)?;
+
+        self.present_dirty(env, &dirty_rects, scroll)
+    }
+    /// Records `f` into a picture and skips drawing and presenting when it matches the last
+    /// recorded frame. Any change repaints the whole frame, see `draw_recorded_regions`.
+    pub fn draw_recorded(
+        &mut self,
+        env: &mut Backend,
+        f: impl FnOnce(&Canvas),
+    ) -> Result<PresentOutcome> {
+        self.draw_recorded_regions(env, |regions| regions.whole(f))
+    }
+    /// Records the frame as regions and only repaints and presents the regions that changed
+    /// since the last recorded frame, taking the buffer age into account. Returns
+    /// `PresentOutcome::Unchanged` without presenting when nothing changed.
+    pub fn draw_recorded_regions(
+        &mut self,
+        env: &mut Backend,
+        f: impl FnOnce(&mut FrameRegions),
+    ) -> Result<PresentOutcome> {
+        let mut regions = FrameRegions::new(self.width(), self.height());
+        f(&mut regions);
+
+        let changed = match self.recorder.changed(&regions) {
+            Some(changed) if changed.is_empty() => return Ok(PresentOutcome::Unchanged),
+            changed => changed.unwrap_or_default(),
+        };
+        let Some((changed, _)) = self.partial_presentation(&changed, None) else {
+            return Ok(PresentOutcome::Unchanged);
+        };
+        // The back buffer may be several frames old, so it also needs what changed since.
+        let repaint = self.frame().repaint(&changed);
+        self.draw_clipped(&repaint, |canvas| regions.playback(canvas))?;
+
+        let outcome = self.present_dirty(env, &changed, None)?;
+        if outcome != PresentOutcome::StillDrawing {
+            self.recorder.presented(regions);
+        }
+        Ok(outcome)
+    }
+    fn draw_clipped(&mut self, clip: &Damage, f: impl FnOnce(&Canvas)) -> Result<()> {
+        let surface = self.get_surface()?;
 
         let canvas = surface.canvas();
+        let save_count = canvas.save();
+        if let Some(rects) = clip.rects() {
+            let mut region = Region::new();
+            region.set_rects(rects);
+            canvas.clip_region(&region, None);
+        }
 
-        f(&canvas);
+        f(canvas);
 
-        env.flush_and_submit_surface(surface, None);
-        unsafe { self.swap_chain.Present(1, 0) }
+        canvas.restore_to_count(save_count);
+        Ok(())
     }
-    pub fn present(&mut self, env: &mut Backend) {
-        let surface = self.get_surface();
-        env.flush_and_submit_surface(surface, None);
-        unsafe { self.swap_chain.Present(1, 0) }.ok().unwrap()
+    pub fn present(&mut self, env: &mut Backend) -> Result<PresentOutcome> {
+        self.present_dirty(env, &[], None)
     }
-    pub fn get_surface(&mut self) -> &mut Surface {
+    /// Asks DXGI whether a present would be shown, without presenting anything.
+    pub fn is_occluded(&self, env: &mut Backend) -> Result<bool> {
+        match unsafe { self.swap_chain.Present(0, DXGI_PRESENT_TEST) } {
+            DXGI_STATUS_OCCLUDED => Ok(true),
+            result => result
+                .ok()
+                .map(|()| false)
+                .map_err(|error| env.device_error(error.into())),
+        }
+    }
+    pub fn present_dirty(
+        &mut self,
+        env: &mut Backend,
+        dirty_rects: &[IRect],
+        scroll: Option<&ScrollRect>,
+    ) -> Result<PresentOutcome> {
+        let Some((dirty_rects, scroll)) = self.partial_presentation(dirty_rects, scroll) else {
+            return Ok(PresentOutcome::Unchanged);
+        };
+        // Whatever is presented now is no longer the last recorded frame.
+        self.recorder.reset();
+
+        let surface = self.get_surface()?;
+        env.flush_and_submit_surface(surface, None)?;
+
+        let mut flags = self.present_mode.present_flags();
+        if self.do_not_wait {
+            flags |= DXGI_PRESENT_DO_NOT_WAIT;
+        }
+        let mut parameters =
+            PresentParameters::new(self.width(), self.height(), &dirty_rects, scroll);
+        let index = unsafe { self.swap_chain.GetCurrentBackBufferIndex() };
+
+        let result = unsafe {
+            self.swap_chain.Present1(
+                self.present_mode.sync_interval(),
+                flags,
+                &parameters.as_dxgi(),
+            )
+        };
+        // An occluded present still flips the buffers, so its damage is recorded too.
+        let outcome = match result {
+            DXGI_STATUS_OCCLUDED => PresentOutcome::Occluded,
+            DXGI_ERROR_WAS_STILL_DRAWING => return Ok(PresentOutcome::StillDrawing),
+            result => {
+                result
+                    .ok()
+                    .map_err(|error| env.device_error(error.into()))?;
+                PresentOutcome::Presented
+            }
+        };
+
+        // Scrolling moves content around, so treat it as a full redraw.
+        let damage = match scroll {
+            Some(_) => Damage::Full,
+            None => Damage::from_rects(parameters.dirty_rects()),
+        };
+        self.damage_history.record(index, damage);
+        Ok(outcome)
+    }
+    // DXGI only accepts dirty rects and scrolling on FLIP_SEQUENTIAL swap chains. Rects are
+    // clipped to the buffer up front, since an empty list would present all of it. None
+    // means every rect was outside the buffer and there is nothing to draw or present.
+    fn partial_presentation<'a>(
+        &self,
+        dirty_rects: &[IRect],
+        scroll: Option<&'a ScrollRect>,
+    ) -> Option<(Vec<IRect>, Option<&'a ScrollRect>)> {
+        if self.desc.SwapEffect != DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL {
+            return Some((Vec::new(), None));
+        }
+        if dirty_rects.is_empty() {
+            return Some((Vec::new(), scroll));
+        }
+        let bounds = IRect::from_wh(self.width() as _, self.height() as _);
+        let clipped = clip_rects(&bounds, dirty_rects);
+        (!clipped.is_empty()).then_some((clipped, scroll))
+    }
+    pub fn get_surface(&mut self) -> Result<&mut Surface> {
         let index = unsafe { self.swap_chain.GetCurrentBackBufferIndex() };
-        &mut self.surfaces.as_mut().unwrap()[index as usize].0
+        self.surfaces
+            .as_mut()
+            .map(|surfaces| &mut surfaces[index as usize].0)
+            .ok_or(Error::ContextLost)
     }
 }
 
-pub(crate) fn swap_chain_desc_hwnd(width: u32, height: u32) -> DXGI_SWAP_CHAIN_DESC1 {
-    swap_chain_desc(width, height, DXGI_ALPHA_MODE_UNSPECIFIED)
-}
+struct FrameLatencyWaitable(HANDLE);
+impl FrameLatencyWaitable {
+    fn new(swap_chain: &IDXGISwapChain3, max_frame_latency: u32) -> Result<Self> {
+        unsafe { swap_chain.SetMaximumFrameLatency(max_frame_latency) }?;
 
-pub(crate) fn swap_chain_desc_composition(width: u32, height: u32) -> DXGI_SWAP_CHAIN_DESC1 {
-    swap_chain_desc(width, height, DXGI_ALPHA_MODE_UNSPECIFIED)
-}
+        Ok(Self(unsafe { swap_chain.GetFrameLatencyWaitableObject() }))
+    }
+    fn wait(&self, timeout: Option<Duration>) -> Result<bool> {
+        let milliseconds = timeout.map_or(u32::MAX, |timeout| {
+            timeout.as_millis().try_into().unwrap_or(u32::MAX - 1)
+        });
 
-fn swap_chain_desc(width: u32, height: u32, alpha_mode: DXGI_ALPHA_MODE) -> DXGI_SWAP_CHAIN_DESC1 {
-    DXGI_SWAP_CHAIN_DESC1 {
-        Width: width,
-        Height: height,
-        Format: DXGI_FORMAT_R8G8B8A8_UNORM,
-        BufferUsage: DXGI_USAGE_RENDER_TARGET_OUTPUT,
-        BufferCount: BUFFER_COUNT,
-        SwapEffect: DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL,
-        SampleDesc: DXGI_SAMPLE_DESC {
-            Count: 1,
-            Quality: 0,
-        },
-        AlphaMode: alpha_mode,
-        ..Default::default()
+        // Not alertable: an APC would end the wait with WAIT_IO_COMPLETION, which is neither a
+        // timeout nor an error.
+        match unsafe { WaitForSingleObjectEx(self.0, milliseconds, false) } {
+            WAIT_OBJECT_0 => Ok(true),
+            WAIT_TIMEOUT => Ok(false),
+            _ => Err(windows::core::Error::from_win32().into()),
+        }
+    }
+}
+impl Drop for FrameLatencyWaitable {
+    fn drop(&mut self) {
+        let _ = unsafe { CloseHandle(self.0) };
     }
 }
@@ -0,0 +1,214 @@
+use std::collections::VecDeque;
+
+use skia_safe::IRect;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Damage {
+    Full,
+    Rects(Vec<IRect>),
+}
+impl Damage {
+    // Empty dirty rects mean the whole buffer was presented.
+    pub(crate) fn from_rects(rects: &[IRect]) -> Self {
+        if rects.is_empty() {
+            Self::Full
+        } else {
+            Self::Rects(rects.to_vec())
+        }
+    }
+    pub fn is_full(&self) -> bool {
+        matches!(self, Self::Full)
+    }
+    pub fn rects(&self) -> Option<&[IRect]> {
+        match self {
+            Self::Full => None,
+            Self::Rects(rects) => Some(rects),
+        }
+    }
+    pub fn union(&self, other: &Self) -> Self {
+        match (self, other) {
+            (Self::Rects(a), Self::Rects(b)) => Self::Rects([&a[..], b].concat()),
+            _ => Self::Full,
+        }
+    }
+}
+
+/// What is in the back buffer about to be drawn, relative to the frames presented so far.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    buffer_age: u32,
+    damage: Damage,
+}
+impl Frame {
+    pub(crate) fn undefined() -> Self {
+        Self {
+            buffer_age: 0,
+            damage: Damage::Full,
+        }
+    }
+    /// How many presents ago this buffer was shown, or 0 if its contents are undefined.
+    pub fn buffer_age(&self) -> u32 {
+        self.buffer_age
+    }
+    /// Everything presented since this buffer was last shown. `Damage::Full` when the buffer
+    /// age is 0.
+    pub fn damage(&self) -> &Damage {
+        &self.damage
+    }
+    /// What has to be repainted to bring this buffer up to date when `changed` is new.
+    pub fn repaint(&self, changed: &[IRect]) -> Damage {
+        self.damage.union(&Damage::from_rects(changed))
+    }
+}
+
+pub(crate) struct DamageHistory {
+    preserves_contents: bool,
+    presents: u64,
+    // When each back buffer was last presented, by buffer index.
+    last_presented: Vec<Option<u64>>,
+    // Damage of the most recent presents, newest last.
+    damage: VecDeque<Damage>,
+}
+impl DamageHistory {
+    // Flip discard swap chains leave back buffers undefined after every present.
+    pub(crate) fn new(buffer_count: u32, preserves_contents: bool) -> Self {
+        Self {
+            preserves_contents,
+            presents: 0,
+            last_presented: vec![None; buffer_count as usize],
+            damage: VecDeque::with_capacity(buffer_count as usize),
+        }
+    }
+    pub(crate) fn reset(&mut self) {
+        self.presents = 0;
+        self.last_presented.fill(None);
+        self.damage.clear();
+    }
+    pub(crate) fn record(&mut self, buffer_index: u32, damage: Damage) {
+        if let Some(last_presented) = self.last_presented.get_mut(buffer_index as usize) {
+            *last_presented = Some(self.presents);
+        }
+        self.presents += 1;
+
+        if self.damage.len() == self.last_presented.len() {
+            self.damage.pop_front();
+        }
+        self.damage.push_back(damage);
+    }
+    pub(crate) fn frame(&self, buffer_index: u32) -> Frame {
+        let last_presented = match self.last_presented.get(buffer_index as usize) {
+            Some(Some(last_presented)) if self.preserves_contents => *last_presented,
+            _ => return Frame::undefined(),
+        };
+        let buffer_age = self.presents - last_presented;
+
+        // The buffer already holds its own frame, so only later presents count.
+        let since = buffer_age as usize - 1;
+        if since > self.damage.len() {
+            return Frame::undefined();
+        }
+        let damage = self
+            .damage
+            .iter()
+            .rev()
+            .take(since)
+            .fold(Damage::Rects(Vec::new()), |union, damage| {
+                union.union(damage)
+            });
+
+        Frame {
+            buffer_age: buffer_age as u32,
+            damage,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: IRect = IRect::new(0, 0, 10, 10);
+    const B: IRect = IRect::new(10, 0, 20, 10);
+    const C: IRect = IRect::new(0, 10, 10, 20);
+
+    fn rects(rects: &[IRect]) -> Damage {
+        Damage::Rects(rects.to_vec())
+    }
+
+    #[test]
+    fn new_and_reset_histories_are_undefined() {
+        let mut history = DamageHistory::new(2, true);
+        assert_eq!(history.frame(0), Frame::undefined());
+        assert_eq!(history.frame(1), Frame::undefined());
+
+        history.record(0, rects(&[A]));
+        history.record(1, rects(&[B]));
+        history.reset();
+        assert_eq!(history.frame(0), Frame::undefined());
+        assert_eq!(history.frame(1).buffer_age(), 0);
+    }
+
+    #[test]
+    fn unpresented_buffer_is_undefined() {
+        let mut history = DamageHistory::new(2, true);
+        history.record(0, rects(&[A]));
+
+        assert_eq!(history.frame(1), Frame::undefined());
+    }
+
+    #[test]
+    fn two_buffers_rotate_with_age_two() {
+        let mut history = DamageHistory::new(2, true);
+        history.record(0, rects(&[A]));
+        history.record(1, rects(&[B]));
+
+        let frame = history.frame(0);
+        assert_eq!(frame.buffer_age(), 2);
+        assert_eq!(frame.damage(), &rects(&[B]));
+
+        history.record(0, rects(&[C]));
+
+        let frame = history.frame(1);
+        assert_eq!(frame.buffer_age(), 2);
+        assert_eq!(frame.damage(), &rects(&[C]));
+    }
+
+    #[test]
+    fn three_buffers_union_damage_since_last_shown() {
+        let mut history = DamageHistory::new(3, true);
+        history.record(0, rects(&[A]));
+        history.record(1, rects(&[B]));
+        history.record(2, rects(&[C]));
+
+        let frame = history.frame(0);
+        assert_eq!(frame.buffer_age(), 3);
+        assert_eq!(frame.damage(), &rects(&[C, B]));
+        assert_eq!(frame.repaint(&[A]), rects(&[C, B, A]));
+    }
+
+    #[test]
+    fn flip_discard_is_always_undefined() {
+        let mut history = DamageHistory::new(2, false);
+        history.record(0, rects(&[A]));
+        history.record(1, rects(&[B]));
+
+        assert_eq!(history.frame(0), Frame::undefined());
+        assert_eq!(history.frame(1), Frame::undefined());
+    }
+
+    #[test]
+    fn full_damage_absorbs_rects() {
+        let mut history = DamageHistory::new(3, true);
+        history.record(0, rects(&[A]));
+        history.record(1, Damage::Full);
+        history.record(2, rects(&[C]));
+
+        let frame = history.frame(0);
+        assert_eq!(frame.buffer_age(), 3);
+        assert!(frame.damage().is_full());
+
+        assert_eq!(rects(&[A]).union(&Damage::Full), Damage::Full);
+        assert_eq!(Damage::Full.union(&rects(&[A])), Damage::Full);
+        assert_eq!(Damage::from_rects(&[]), Damage::Full);
+    }
+}
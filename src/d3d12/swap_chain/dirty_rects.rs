@@ -0,0 +1,98 @@
+use skia_safe::{IRect, IVector};
+use windows::Win32::{
+    Foundation::{POINT, RECT},
+    Graphics::Dxgi::DXGI_PRESENT_PARAMETERS,
+};
+
+// Content inside `rect` moved by `offset` since the previous present.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScrollRect {
+    pub rect: IRect,
+    pub offset: IVector,
+}
+
+// Owns the arrays DXGI_PRESENT_PARAMETERS points into.
+pub(crate) struct PresentParameters {
+    clipped_rects: Vec<IRect>,
+    dirty_rects: Vec<RECT>,
+    scroll_rect: Option<RECT>,
+    scroll_offset: Option<POINT>,
+}
+impl PresentParameters {
+    // Rects are clipped to the buffer, which Present1 requires. No rects means the whole
+    // buffer changed.
+    pub(crate) fn new(
+        width: u32,
+        height: u32,
+        dirty_rects: &[IRect],
+        scroll: Option<&ScrollRect>,
+    ) -> Self {
+        let bounds = IRect::from_wh(width as _, height as _);
+        let to_rect = |rect: IRect| RECT {
+            left: rect.left,
+            top: rect.top,
+            right: rect.right,
+            bottom: rect.bottom,
+        };
+        let scroll = scroll
+            .and_then(|scroll| Some((IRect::intersect(&bounds, &scroll.rect)?, scroll.offset)));
+
+        let clipped_rects = clip_rects(&bounds, dirty_rects);
+
+        Self {
+            dirty_rects: clipped_rects.iter().copied().map(to_rect).collect(),
+            clipped_rects,
+            scroll_rect: scroll.map(|(rect, _)| to_rect(rect)),
+            scroll_offset: scroll.map(|(_, offset)| POINT {
+                x: offset.x,
+                y: offset.y,
+            }),
+        }
+    }
+    pub(crate) fn dirty_rects(&self) -> &[IRect] {
+        &self.clipped_rects
+    }
+    pub(crate) fn as_dxgi(&mut self) -> DXGI_PRESENT_PARAMETERS {
+        DXGI_PRESENT_PARAMETERS {
+            DirtyRectsCount: self.dirty_rects.len() as _,
+            pDirtyRects: if self.dirty_rects.is_empty() {
+                std::ptr::null_mut()
+            } else {
+                self.dirty_rects.as_mut_ptr()
+            },
+            pScrollRect: self
+                .scroll_rect
+                .as_mut()
+                .map_or(std::ptr::null_mut(), |rect| rect),
+            pScrollOffset: self
+                .scroll_offset
+                .as_mut()
+                .map_or(std::ptr::null_mut(), |offset| offset),
+        }
+    }
+}
+
+pub(crate) fn clip_rects(bounds: &IRect, rects: &[IRect]) -> Vec<IRect> {
+    rects
+        .iter()
+        .filter_map(|rect| IRect::intersect(bounds, rect))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clip_rects_drops_empty_and_outside_rects() {
+        let bounds = IRect::from_wh(100, 100);
+        let rects = [
+            IRect::new(90, 90, 110, 110),
+            IRect::new_empty(),
+            IRect::new(200, 0, 210, 10),
+        ];
+
+        assert_eq!(clip_rects(&bounds, &rects), [IRect::new(90, 90, 100, 100)]);
+        assert!(clip_rects(&bounds, &rects[1..]).is_empty());
+    }
+}
@@ -0,0 +1,155 @@
+use skia_safe::{named_transfer_fn, ColorSpace};
+use windows::{
+    core::Interface,
+    Win32::Graphics::Dxgi::{
+        Common::{
+            DXGI_COLOR_SPACE_RGB_FULL_G10_NONE_P709, DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020,
+            DXGI_COLOR_SPACE_TYPE,
+        },
+        IDXGISwapChain3, IDXGISwapChain4, DXGI_HDR_METADATA_HDR10, DXGI_HDR_METADATA_TYPE_HDR10,
+        DXGI_SWAP_CHAIN_COLOR_SPACE_SUPPORT_FLAG_PRESENT,
+    },
+};
+
+use super::options::PixelFormat;
+use crate::{
+    d3d12::color_space,
+    error::{Error, Result},
+};
+
+// PQ encodes absolute luminance up to this peak, which Skia's PQ curve maps to linear 1.0.
+const PQ_PEAK_NITS: f32 = 10000.0;
+// Where linear 1.0 lands on HDR10 output, matching scRGB.
+const HDR10_REFERENCE_WHITE_NITS: f32 = 80.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HdrMode {
+    #[default]
+    Off,
+    // Linear Rec.709 primaries on FP16 buffers, 1.0 is 80 nits.
+    ScRgb,
+    // ST.2084 (PQ) encoded Rec.2020 on 10-bit buffers, with 1.0 at 80 nits like scRGB.
+    Hdr10,
+}
+impl HdrMode {
+    pub fn format(self) -> Option<PixelFormat> {
+        match self {
+            Self::Off => None,
+            Self::ScRgb => Some(PixelFormat::RgbaF16),
+            Self::Hdr10 => Some(PixelFormat::Rgb10A2),
+        }
+    }
+    pub fn color_space(self) -> Option<ColorSpace> {
+        match self {
+            Self::Off => None,
+            Self::ScRgb => Some(ColorSpace::new_srgb_linear()),
+            Self::Hdr10 => Some(color_space::new_rgb(
+                &named_transfer_fn::PQ,
+                &hdr10_to_xyz_d50(),
+            )),
+        }
+    }
+    fn to_dxgi(self) -> Option<DXGI_COLOR_SPACE_TYPE> {
+        match self {
+            Self::Off => None,
+            Self::ScRgb => Some(DXGI_COLOR_SPACE_RGB_FULL_G10_NONE_P709),
+            Self::Hdr10 => Some(DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020),
+        }
+    }
+}
+
+// Mastering display description, in CIE 1931 xy chromaticities and nits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hdr10Metadata {
+    pub red_primary: [f32; 2],
+    pub green_primary: [f32; 2],
+    pub blue_primary: [f32; 2],
+    pub white_point: [f32; 2],
+    pub max_mastering_luminance: f32,
+    pub min_mastering_luminance: f32,
+    pub max_content_light_level: u16,
+    pub max_frame_average_light_level: u16,
+}
+impl Default for Hdr10Metadata {
+    // Rec.2020 primaries with a D65 white point on a 1000 nit display.
+    fn default() -> Self {
+        Self {
+            red_primary: [0.708, 0.292],
+            green_primary: [0.170, 0.797],
+            blue_primary: [0.131, 0.046],
+            white_point: [0.3127, 0.3290],
+            max_mastering_luminance: 1000.0,
+            min_mastering_luminance: 0.001,
+            max_content_light_level: 0,
+            max_frame_average_light_level: 0,
+        }
+    }
+}
+impl Hdr10Metadata {
+    fn to_dxgi(self) -> DXGI_HDR_METADATA_HDR10 {
+        let chromaticity = |[x, y]: [f32; 2]| [(x * 50000.0) as u16, (y * 50000.0) as u16];
+
+        DXGI_HDR_METADATA_HDR10 {
+            RedPrimary: chromaticity(self.red_primary),
+            GreenPrimary: chromaticity(self.green_primary),
+            BluePrimary: chromaticity(self.blue_primary),
+            WhitePoint: chromaticity(self.white_point),
+            MaxMasteringLuminance: (self.max_mastering_luminance * 10000.0) as u32,
+            MinMasteringLuminance: (self.min_mastering_luminance * 10000.0) as u32,
+            MaxContentLightLevel: self.max_content_light_level,
+            MaxFrameAverageLightLevel: self.max_frame_average_light_level,
+        }
+    }
+}
+
+// Scaling the gamut up makes Skia scale linear values down by the same factor when drawing
+// into this space, so SDR white ends up at the reference white instead of the PQ peak.
+fn hdr10_to_xyz_d50() -> [f32; 9] {
+    color_space::REC2020_GAMUT.map(|value| value * PQ_PEAK_NITS / HDR10_REFERENCE_WHITE_NITS)
+}
+
+pub(crate) fn apply(
+    swap_chain: &IDXGISwapChain3,
+    mode: HdrMode,
+    metadata: Option<&Hdr10Metadata>,
+) -> Result<()> {
+    let Some(color_space) = mode.to_dxgi() else {
+        return Ok(());
+    };
+
+    let support = unsafe { swap_chain.CheckColorSpaceSupport(color_space) }?;
+    if support & DXGI_SWAP_CHAIN_COLOR_SPACE_SUPPORT_FLAG_PRESENT.0 as u32 == 0 {
+        return Err(Error::HdrUnsupported);
+    }
+    unsafe { swap_chain.SetColorSpace1(color_space) }?;
+
+    if let Some(metadata) = metadata {
+        let metadata = metadata.to_dxgi();
+        let swap_chain: IDXGISwapChain4 = swap_chain.cast()?;
+        unsafe {
+            swap_chain.SetHDRMetaData(
+                DXGI_HDR_METADATA_TYPE_HDR10,
+                Some(std::slice::from_raw_parts(
+                    &metadata as *const _ as *const u8,
+                    std::mem::size_of_val(&metadata),
+                )),
+            )
+        }?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hdr10_white_is_reference_white() {
+        let to_xyz_d50 = hdr10_to_xyz_d50();
+        let luminance = to_xyz_d50[3] + to_xyz_d50[4] + to_xyz_d50[5];
+
+        // White in this space is 125 times brighter than sRGB white, which therefore ends up
+        // at 1/125 of the PQ range, or 80 nits.
+        assert!((luminance - PQ_PEAK_NITS / HDR10_REFERENCE_WHITE_NITS).abs() < 0.01);
+    }
+}
@@ -0,0 +1,576 @@
+use skia_safe::{ColorSpace, ColorType};
+use windows::Win32::{
+    Foundation::HWND,
+    Graphics::Dxgi::{
+        Common::{
+            DXGI_ALPHA_MODE, DXGI_ALPHA_MODE_IGNORE, DXGI_ALPHA_MODE_PREMULTIPLIED,
+            DXGI_ALPHA_MODE_STRAIGHT, DXGI_ALPHA_MODE_UNSPECIFIED, DXGI_FORMAT,
+            DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_R10G10B10A2_UNORM,
+            DXGI_FORMAT_R16G16B16A16_FLOAT, DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_SAMPLE_DESC,
+        },
+        DXGI_MAX_SWAP_CHAIN_BUFFERS, DXGI_PRESENT_ALLOW_TEARING, DXGI_SCALING,
+        DXGI_SCALING_ASPECT_RATIO_STRETCH, DXGI_SCALING_NONE, DXGI_SCALING_STRETCH,
+        DXGI_SWAP_CHAIN_DESC1, DXGI_SWAP_CHAIN_FLAG_ALLOW_TEARING,
+        DXGI_SWAP_CHAIN_FLAG_FRAME_LATENCY_WAITABLE_OBJECT, DXGI_SWAP_EFFECT,
+        DXGI_SWAP_EFFECT_FLIP_DISCARD, DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL,
+        DXGI_USAGE_RENDER_TARGET_OUTPUT,
+    },
+};
+
+use super::hdr::{Hdr10Metadata, HdrMode};
+use crate::{
+    d3d12::color_space::SurfaceColorSpace,
+    error::{Error, Result},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapChainKind {
+    Hwnd,
+    Composition,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SwapEffect {
+    #[default]
+    FlipSequential,
+    FlipDiscard,
+}
+impl SwapEffect {
+    fn to_dxgi(self) -> DXGI_SWAP_EFFECT {
+        match self {
+            Self::FlipSequential => DXGI_SWAP_EFFECT_FLIP_SEQUENTIAL,
+            Self::FlipDiscard => DXGI_SWAP_EFFECT_FLIP_DISCARD,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Scaling {
+    #[default]
+    Stretch,
+    None,
+    AspectRatioStretch,
+}
+impl Scaling {
+    fn to_dxgi(self) -> DXGI_SCALING {
+        match self {
+            Self::Stretch => DXGI_SCALING_STRETCH,
+            Self::None => DXGI_SCALING_NONE,
+            Self::AspectRatioStretch => DXGI_SCALING_ASPECT_RATIO_STRETCH,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AlphaMode {
+    #[default]
+    Unspecified,
+    Premultiplied,
+    Straight,
+    Ignore,
+}
+impl AlphaMode {
+    fn to_dxgi(self) -> DXGI_ALPHA_MODE {
+        match self {
+            Self::Unspecified => DXGI_ALPHA_MODE_UNSPECIFIED,
+            Self::Premultiplied => DXGI_ALPHA_MODE_PREMULTIPLIED,
+            Self::Straight => DXGI_ALPHA_MODE_STRAIGHT,
+            Self::Ignore => DXGI_ALPHA_MODE_IGNORE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Waits for the given number of vertical blanks, 1 to 4.
+    Vsync(u32),
+    /// Replaces the queued frame at the next vertical blank instead of waiting.
+    Immediate,
+    /// Presents right away, even mid-scanout. Needs DXGI_FEATURE_PRESENT_ALLOW_TEARING, and
+    /// falls back to `Immediate` without it.
+    Tearing,
+}
+impl Default for PresentMode {
+    fn default() -> Self {
+        Self::Vsync(1)
+    }
+}
+impl PresentMode {
+    pub(crate) fn validate(self) -> Result<()> {
+        match self {
+            Self::Vsync(interval) if !(1..=4).contains(&interval) => {
+                Err(Error::InvalidSwapChainOptions(format!(
+                    "PresentMode::Vsync needs a sync interval of 1 to 4, got {interval}, \
+                     use PresentMode::Immediate to present without waiting"
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+    pub(crate) fn sync_interval(self) -> u32 {
+        match self {
+            Self::Vsync(interval) => interval,
+            Self::Immediate | Self::Tearing => 0,
+        }
+    }
+    pub(crate) fn present_flags(self) -> u32 {
+        match self {
+            Self::Tearing => DXGI_PRESENT_ALLOW_TEARING,
+            _ => 0,
+        }
+    }
+    pub(crate) fn swap_chain_flags(self) -> u32 {
+        match self {
+            Self::Tearing => DXGI_SWAP_CHAIN_FLAG_ALLOW_TEARING.0 as _,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PixelFormat {
+    #[default]
+    Rgba8,
+    Bgra8,
+    Rgb10A2,
+    RgbaF16,
+}
+// The back buffer, the texture Skia renders into and the Skia color type have to agree.
+const PIXEL_FORMATS: [(PixelFormat, DXGI_FORMAT, ColorType); 4] = [
+    (
+        PixelFormat::Rgba8,
+        DXGI_FORMAT_R8G8B8A8_UNORM,
+        ColorType::RGBA8888,
+    ),
+    (
+        PixelFormat::Bgra8,
+        DXGI_FORMAT_B8G8R8A8_UNORM,
+        ColorType::BGRA8888,
+    ),
+    (
+        PixelFormat::Rgb10A2,
+        DXGI_FORMAT_R10G10B10A2_UNORM,
+        ColorType::RGBA1010102,
+    ),
+    (
+        PixelFormat::RgbaF16,
+        DXGI_FORMAT_R16G16B16A16_FLOAT,
+        ColorType::RGBAF16,
+    ),
+];
+impl PixelFormat {
+    pub(crate) fn from_dxgi(format: DXGI_FORMAT) -> Option<Self> {
+        PIXEL_FORMATS
+            .iter()
+            .find(|(_, dxgi, _)| *dxgi == format)
+            .map(|(pixel_format, _, _)| *pixel_format)
+    }
+    pub(crate) fn to_dxgi(self) -> DXGI_FORMAT {
+        self.entry().1
+    }
+    pub fn color_type(self) -> ColorType {
+        self.entry().2
+    }
+    fn entry(self) -> &'static (PixelFormat, DXGI_FORMAT, ColorType) {
+        // Rows are in declaration order, so the discriminant is the index. Checked by
+        // `pixel_format_rows_are_indexed_by_discriminant`.
+        &PIXEL_FORMATS[self as usize]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwapChainOptions {
+    pub buffer_count: u32,
+    pub format: PixelFormat,
+    pub swap_effect: SwapEffect,
+    pub scaling: Scaling,
+    pub alpha_mode: AlphaMode,
+    // DXGI_SWAP_CHAIN_FLAG bits, combined with the flags the backend needs itself.
+    pub flags: u32,
+    pub stereo: bool,
+    pub hdr: HdrMode,
+    pub hdr_metadata: Option<Hdr10Metadata>,
+    pub color_space: SurfaceColorSpace,
+    pub present_mode: PresentMode,
+    // Return DXGI_ERROR_WAS_STILL_DRAWING from present instead of blocking on a full queue.
+    pub do_not_wait: bool,
+    // Opts into a frame latency waitable object that allows this many queued frames.
+    pub max_frame_latency: Option<u32>,
+}
+impl Default for SwapChainOptions {
+    fn default() -> Self {
+        Self {
+            buffer_count: 2,
+            format: PixelFormat::default(),
+            swap_effect: SwapEffect::default(),
+            scaling: Scaling::default(),
+            alpha_mode: AlphaMode::default(),
+            flags: 0,
+            stereo: false,
+            hdr: HdrMode::default(),
+            hdr_metadata: None,
+            color_space: SurfaceColorSpace::default(),
+            present_mode: PresentMode::default(),
+            do_not_wait: false,
+            max_frame_latency: None,
+        }
+    }
+}
+impl SwapChainOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn buffer_count(mut self, buffer_count: u32) -> Self {
+        self.buffer_count = buffer_count;
+        self
+    }
+    pub fn format(mut self, format: PixelFormat) -> Self {
+        self.format = format;
+        self
+    }
+    pub fn swap_effect(mut self, swap_effect: SwapEffect) -> Self {
+        self.swap_effect = swap_effect;
+        self
+    }
+    pub fn scaling(mut self, scaling: Scaling) -> Self {
+        self.scaling = scaling;
+        self
+    }
+    pub fn alpha_mode(mut self, alpha_mode: AlphaMode) -> Self {
+        self.alpha_mode = alpha_mode;
+        self
+    }
+    pub fn flags(mut self, flags: u32) -> Self {
+        self.flags = flags;
+        self
+    }
+    pub fn stereo(mut self, stereo: bool) -> Self {
+        self.stereo = stereo;
+        self
+    }
+    // Also switches to the pixel format the mode is defined for.
+    pub fn hdr(mut self, hdr: HdrMode) -> Self {
+        self.hdr = hdr;
+        if let Some(format) = hdr.format() {
+            self.format = format;
+        }
+        self
+    }
+    pub fn hdr_metadata(mut self, hdr_metadata: impl Into<Option<Hdr10Metadata>>) -> Self {
+        self.hdr_metadata = hdr_metadata.into();
+        self
+    }
+    pub fn color_space(mut self, color_space: SurfaceColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
+    pub fn present_mode(mut self, present_mode: PresentMode) -> Self {
+        self.present_mode = present_mode;
+        self
+    }
+    pub fn do_not_wait(mut self, do_not_wait: bool) -> Self {
+        self.do_not_wait = do_not_wait;
+        self
+    }
+    pub fn max_frame_latency(mut self, max_frame_latency: impl Into<Option<u32>>) -> Self {
+        self.max_frame_latency = max_frame_latency.into();
+        self
+    }
+    pub fn validate(&self, kind: SwapChainKind) -> Result<()> {
+        let invalid = |message: String| Err(Error::InvalidSwapChainOptions(message));
+
+        if !(2..=DXGI_MAX_SWAP_CHAIN_BUFFERS).contains(&self.buffer_count) {
+            return invalid(format!(
+                "flip model swap chains need 2 to {DXGI_MAX_SWAP_CHAIN_BUFFERS} buffers, got {}",
+                self.buffer_count
+            ));
+        }
+        self.present_mode.validate()?;
+        if let Some(max_frame_latency) = self.max_frame_latency {
+            if !(1..=16).contains(&max_frame_latency) {
+                return invalid(format!(
+                    "the maximum frame latency must be 1 to 16, got {max_frame_latency}"
+                ));
+            }
+        }
+        if self.stereo && self.swap_effect != SwapEffect::FlipSequential {
+            return invalid("stereo swap chains require SwapEffect::FlipSequential".into());
+        }
+        if let Some(format) = self.hdr.format() {
+            if self.format != format {
+                return invalid(format!(
+                    "HdrMode::{:?} requires PixelFormat::{format:?}, got PixelFormat::{:?}",
+                    self.hdr, self.format
+                ));
+            }
+            if self.color_space != SurfaceColorSpace::Unmanaged {
+                return invalid("HDR modes define their own color space".into());
+            }
+        } else if self.hdr_metadata.is_some() {
+            return invalid("HDR metadata requires an HdrMode other than HdrMode::Off".into());
+        }
+        match kind {
+            SwapChainKind::Hwnd => {
+                if !matches!(self.alpha_mode, AlphaMode::Unspecified | AlphaMode::Ignore) {
+                    return invalid(format!(
+                        "window swap chains cannot use AlphaMode::{:?}, \
+                         only composition swap chains support transparency",
+                        self.alpha_mode
+                    ));
+                }
+            }
+            SwapChainKind::Composition => {
+                if self.scaling != Scaling::Stretch {
+                    return invalid(format!(
+                        "composition swap chains require Scaling::Stretch, got Scaling::{:?}",
+                        self.scaling
+                    ));
+                }
+                // Skia always renders premultiplied colors, and DirectComposition only
+                // blends premultiplied content.
+                if self.alpha_mode == AlphaMode::Straight {
+                    return invalid(
+                        "composition swap chains cannot use AlphaMode::Straight".into(),
+                    );
+                }
+                if self.color_space == SurfaceColorSpace::Monitor {
+                    return invalid(
+                        "composition swap chains are not tied to a window, \
+                         SurfaceColorSpace::Monitor needs one"
+                            .into(),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+    pub(crate) fn skia_color_space(&self, hwnd: Option<HWND>) -> Result<Option<ColorSpace>> {
+        match self.hdr {
+            HdrMode::Off => self.color_space.resolve(hwnd),
+            hdr => Ok(hdr.color_space()),
+        }
+    }
+    pub(crate) fn tracks_monitor(&self) -> bool {
+        self.hdr == HdrMode::Off && self.color_space == SurfaceColorSpace::Monitor
+    }
+    pub(crate) fn desc(
+        &self,
+        kind: SwapChainKind,
+        width: u32,
+        height: u32,
+        flags: u32,
+    ) -> DXGI_SWAP_CHAIN_DESC1 {
+        let alpha_mode = match (kind, self.alpha_mode) {
+            // Composition swap chains blend with what is behind them unless told otherwise.
+            (SwapChainKind::Composition, AlphaMode::Unspecified) => AlphaMode::Premultiplied,
+            (_, alpha_mode) => alpha_mode,
+        };
+
+        let mut flags = self.flags | flags;
+        if self.max_frame_latency.is_some() {
+            flags |= DXGI_SWAP_CHAIN_FLAG_FRAME_LATENCY_WAITABLE_OBJECT.0 as u32;
+        }
+
+        DXGI_SWAP_CHAIN_DESC1 {
+            Width: width,
+            Height: height,
+            Format: self.format.to_dxgi(),
+            Stereo: self.stereo.into(),
+            BufferUsage: DXGI_USAGE_RENDER_TARGET_OUTPUT,
+            BufferCount: self.buffer_count,
+            Scaling: self.scaling.to_dxgi(),
+            SwapEffect: self.swap_effect.to_dxgi(),
+            SampleDesc: DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            AlphaMode: alpha_mode.to_dxgi(),
+            Flags: flags,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_PIXEL_FORMATS: [PixelFormat; 4] = [
+        PixelFormat::Rgba8,
+        PixelFormat::Bgra8,
+        PixelFormat::Rgb10A2,
+        PixelFormat::RgbaF16,
+    ];
+
+    #[test]
+    fn pixel_format_rows_are_indexed_by_discriminant() {
+        // Stops compiling when a variant is added without updating ALL_PIXEL_FORMATS.
+        match PixelFormat::default() {
+            PixelFormat::Rgba8
+            | PixelFormat::Bgra8
+            | PixelFormat::Rgb10A2
+            | PixelFormat::RgbaF16 => {}
+        }
+        assert_eq!(PIXEL_FORMATS.len(), ALL_PIXEL_FORMATS.len());
+        for format in ALL_PIXEL_FORMATS {
+            assert_eq!(PIXEL_FORMATS[format as usize].0, format);
+        }
+    }
+
+    #[test]
+    fn pixel_formats_round_trip_through_dxgi() {
+        for format in ALL_PIXEL_FORMATS {
+            assert_eq!(PixelFormat::from_dxgi(format.to_dxgi()), Some(format));
+        }
+        assert_eq!(PixelFormat::from_dxgi(DXGI_FORMAT(0)), None);
+    }
+
+    #[test]
+    fn pixel_format_color_types() {
+        assert_eq!(PixelFormat::Rgba8.color_type(), ColorType::RGBA8888);
+        assert_eq!(PixelFormat::Bgra8.color_type(), ColorType::BGRA8888);
+        assert_eq!(PixelFormat::Rgb10A2.color_type(), ColorType::RGBA1010102);
+        assert_eq!(PixelFormat::RgbaF16.color_type(), ColorType::RGBAF16);
+    }
+
+    fn rejects(options: SwapChainOptions, kind: SwapChainKind) -> bool {
+        matches!(
+            options.validate(kind),
+            Err(Error::InvalidSwapChainOptions(_))
+        )
+    }
+
+    #[test]
+    fn default_options_are_valid() {
+        assert_eq!(
+            SwapChainOptions::new().validate(SwapChainKind::Hwnd),
+            Ok(())
+        );
+        assert_eq!(
+            SwapChainOptions::new().validate(SwapChainKind::Composition),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rejects_buffer_count_out_of_range() {
+        for buffer_count in [0, 1, DXGI_MAX_SWAP_CHAIN_BUFFERS + 1] {
+            let options = SwapChainOptions::new().buffer_count(buffer_count);
+            assert!(rejects(options, SwapChainKind::Hwnd));
+        }
+        for buffer_count in [2, DXGI_MAX_SWAP_CHAIN_BUFFERS] {
+            let options = SwapChainOptions::new().buffer_count(buffer_count);
+            assert_eq!(options.validate(SwapChainKind::Hwnd), Ok(()));
+        }
+    }
+
+    #[test]
+    fn rejects_vsync_interval_out_of_range() {
+        for interval in [0, 5] {
+            let options = SwapChainOptions::new().present_mode(PresentMode::Vsync(interval));
+            assert!(rejects(options, SwapChainKind::Hwnd));
+        }
+        let options = SwapChainOptions::new().present_mode(PresentMode::Vsync(4));
+        assert_eq!(options.validate(SwapChainKind::Hwnd), Ok(()));
+    }
+
+    #[test]
+    fn rejects_frame_latency_out_of_range() {
+        for max_frame_latency in [0, 17] {
+            let options = SwapChainOptions::new().max_frame_latency(max_frame_latency);
+            assert!(rejects(options, SwapChainKind::Hwnd));
+        }
+        let options = SwapChainOptions::new().max_frame_latency(1);
+        assert_eq!(options.validate(SwapChainKind::Hwnd), Ok(()));
+    }
+
+    #[test]
+    fn rejects_stereo_with_flip_discard() {
+        let options = SwapChainOptions::new()
+            .stereo(true)
+            .swap_effect(SwapEffect::FlipDiscard);
+        assert!(rejects(options, SwapChainKind::Hwnd));
+
+        let options = SwapChainOptions::new().stereo(true);
+        assert_eq!(options.validate(SwapChainKind::Hwnd), Ok(()));
+    }
+
+    #[test]
+    fn rejects_hdr_with_mismatched_format() {
+        let options = SwapChainOptions::new()
+            .hdr(HdrMode::Hdr10)
+            .format(PixelFormat::Rgba8);
+        assert!(rejects(options, SwapChainKind::Hwnd));
+
+        let options = SwapChainOptions::new().hdr(HdrMode::ScRgb);
+        assert_eq!(options.validate(SwapChainKind::Hwnd), Ok(()));
+    }
+
+    #[test]
+    fn rejects_hdr_with_color_space() {
+        let options = SwapChainOptions::new()
+            .hdr(HdrMode::Hdr10)
+            .color_space(SurfaceColorSpace::Srgb);
+        assert!(rejects(options, SwapChainKind::Hwnd));
+    }
+
+    #[test]
+    fn rejects_hdr_metadata_without_hdr() {
+        let options = SwapChainOptions::new().hdr_metadata(Hdr10Metadata::default());
+        assert!(rejects(options, SwapChainKind::Hwnd));
+
+        let options = SwapChainOptions::new()
+            .hdr(HdrMode::Hdr10)
+            .hdr_metadata(Hdr10Metadata::default());
+        assert_eq!(options.validate(SwapChainKind::Hwnd), Ok(()));
+    }
+
+    #[test]
+    fn rejects_transparent_hwnd_alpha_modes() {
+        for alpha_mode in [AlphaMode::Premultiplied, AlphaMode::Straight] {
+            let options = SwapChainOptions::new().alpha_mode(alpha_mode);
+            assert!(rejects(options, SwapChainKind::Hwnd));
+        }
+        let options = SwapChainOptions::new().alpha_mode(AlphaMode::Ignore);
+        assert_eq!(options.validate(SwapChainKind::Hwnd), Ok(()));
+    }
+
+    #[test]
+    fn rejects_composition_scaling() {
+        for scaling in [Scaling::None, Scaling::AspectRatioStretch] {
+            let options = SwapChainOptions::new().scaling(scaling);
+            assert!(rejects(options.clone(), SwapChainKind::Composition));
+            assert_eq!(options.validate(SwapChainKind::Hwnd), Ok(()));
+        }
+    }
+
+    #[test]
+    fn rejects_composition_straight_alpha() {
+        let options = SwapChainOptions::new().alpha_mode(AlphaMode::Straight);
+        assert!(rejects(options, SwapChainKind::Composition));
+
+        let options = SwapChainOptions::new().alpha_mode(AlphaMode::Premultiplied);
+        assert_eq!(options.validate(SwapChainKind::Composition), Ok(()));
+    }
+
+    #[test]
+    fn rejects_composition_monitor_color_space() {
+        let options = SwapChainOptions::new().color_space(SurfaceColorSpace::Monitor);
+        assert!(rejects(options.clone(), SwapChainKind::Composition));
+        assert_eq!(options.validate(SwapChainKind::Hwnd), Ok(()));
+    }
+
+    #[test]
+    fn composition_desc_defaults_to_premultiplied() {
+        let options = SwapChainOptions::new();
+
+        let desc = options.desc(SwapChainKind::Composition, 640, 480, 0);
+        assert_eq!(desc.AlphaMode, DXGI_ALPHA_MODE_PREMULTIPLIED);
+
+        let desc = options.desc(SwapChainKind::Hwnd, 640, 480, 0);
+        assert_eq!(desc.AlphaMode, DXGI_ALPHA_MODE_UNSPECIFIED);
+
+        let options = SwapChainOptions::new().alpha_mode(AlphaMode::Ignore);
+        let desc = options.desc(SwapChainKind::Composition, 640, 480, 0);
+        assert_eq!(desc.AlphaMode, DXGI_ALPHA_MODE_IGNORE);
+    }
+}
@@ -0,0 +1,177 @@
+use skia_safe::{Canvas, Data, IRect, Picture, PictureRecorder, Rect};
+
+use super::dirty_rects::clip_rects;
+
+// skia-safe cannot build a bounding box hierarchy or walk the ops of a picture, so damage is
+// tracked per region instead: every region is recorded into its own picture and compared
+// with the region at the same position in the last presented frame.
+
+/// A frame recorded as a list of regions, see `SwapChain::draw_recorded_regions`.
+pub struct FrameRegions {
+    bounds: IRect,
+    keys: Vec<(IRect, Option<Data>)>,
+    pictures: Vec<Picture>,
+}
+impl FrameRegions {
+    pub(crate) fn new(width: u32, height: u32) -> Self {
+        Self {
+            bounds: IRect::from_wh(width as _, height as _),
+            keys: Vec::new(),
+            pictures: Vec::new(),
+        }
+    }
+    /// The whole surface.
+    pub fn bounds(&self) -> IRect {
+        self.bounds
+    }
+    /// Records `f` clipped to `bounds`. The region is only repainted when its drawing differs
+    /// from the region at the same position in the last presented frame. Recordings are
+    /// compared in serialized form, which encodes raster images every frame and may not
+    /// capture the pixels of texture-backed images, so use `volatile_region` for those.
+    pub fn region(&mut self, bounds: IRect, f: impl FnOnce(&Canvas)) {
+        let picture = Self::record(bounds, f);
+        self.keys.push((bounds, Some(picture.serialize())));
+        self.pictures.push(picture);
+    }
+    /// Records `f` clipped to `bounds` and repaints it every frame without comparing it, for
+    /// video, texture-backed images and other content that changes all the time.
+    pub fn volatile_region(&mut self, bounds: IRect, f: impl FnOnce(&Canvas)) {
+        self.keys.push((bounds, None));
+        self.pictures.push(Self::record(bounds, f));
+    }
+    pub(crate) fn whole(&mut self, f: impl FnOnce(&Canvas)) {
+        self.region(self.bounds, f);
+    }
+    fn record(bounds: IRect, f: impl FnOnce(&Canvas)) -> Picture {
+        let cull = Rect::from_irect(bounds);
+
+        let mut recorder = PictureRecorder::new();
+        let canvas = recorder.begin_recording(cull, None);
+        canvas.clip_irect(bounds, None);
+        f(canvas);
+
+        recorder
+            .finish_recording_as_picture(None)
+            .unwrap_or_else(|| Picture::new_placeholder(cull))
+    }
+    pub(crate) fn playback(&self, canvas: &Canvas) {
+        for picture in &self.pictures {
+            picture.playback(canvas);
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct FrameRecorder {
+    presented: Option<Vec<(IRect, Option<Data>)>>,
+}
+impl FrameRecorder {
+    // The bounds that changed since the last presented frame, clipped to the surface, or None
+    // when there is nothing to compare with.
+    pub(crate) fn changed(&self, frame: &FrameRegions) -> Option<Vec<IRect>> {
+        let presented = self.presented.as_ref()?;
+        Some(changed_regions(&frame.bounds, presented, &frame.keys))
+    }
+    pub(crate) fn presented(&mut self, frame: FrameRegions) {
+        self.presented = Some(frame.keys);
+    }
+    pub(crate) fn reset(&mut self) {
+        self.presented = None;
+    }
+}
+
+// Regions are matched by position. A region that moved, appeared, disappeared, is volatile or
+// drew something else damages both its old and its new bounds. Changes outside `bounds` are
+// not visible and are dropped.
+fn changed_regions<K: PartialEq>(
+    bounds: &IRect,
+    previous: &[(IRect, Option<K>)],
+    current: &[(IRect, Option<K>)],
+) -> Vec<IRect> {
+    let mut changed = Vec::new();
+    for i in 0..previous.len().max(current.len()) {
+        match (previous.get(i), current.get(i)) {
+            (Some((a, Some(x))), Some((b, Some(y)))) if a == b && x == y => {}
+            (Some((a, _)), Some((b, _))) if a == b => changed.push(*b),
+            (previous, current) => changed.extend(
+                previous
+                    .into_iter()
+                    .chain(current)
+                    .map(|(bounds, _)| *bounds),
+            ),
+        }
+    }
+    clip_rects(bounds, &changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: IRect = IRect::new(0, 0, 10, 10);
+    const B: IRect = IRect::new(10, 0, 20, 10);
+    const C: IRect = IRect::new(0, 10, 10, 20);
+    const BOUNDS: IRect = IRect::new(0, 0, 100, 100);
+
+    #[test]
+    fn identical_regions_are_unchanged() {
+        let frame = [(A, Some(1)), (B, Some(2))];
+
+        assert!(changed_regions(&BOUNDS, &frame, &frame).is_empty());
+    }
+
+    #[test]
+    fn changed_drawing_damages_its_bounds() {
+        let previous = [(A, Some(1)), (B, Some(2))];
+        let current = [(A, Some(1)), (B, Some(3))];
+
+        assert_eq!(changed_regions(&BOUNDS, &previous, &current), [B]);
+    }
+
+    #[test]
+    fn moved_region_damages_old_and_new_bounds() {
+        let previous = [(A, Some(1))];
+        let current = [(C, Some(1))];
+
+        assert_eq!(changed_regions(&BOUNDS, &previous, &current), [A, C]);
+    }
+
+    #[test]
+    fn added_and_removed_regions_are_damaged() {
+        let previous = [(A, Some(1))];
+        let current = [(A, Some(1)), (B, Some(2))];
+
+        assert_eq!(changed_regions(&BOUNDS, &previous, &current), [B]);
+        assert_eq!(changed_regions(&BOUNDS, &current, &previous), [B]);
+    }
+
+    #[test]
+    fn volatile_regions_are_always_damaged() {
+        let frame = [(A, Some(1)), (B, None)];
+
+        assert_eq!(changed_regions(&BOUNDS, &frame, &frame), [B]);
+    }
+
+    #[test]
+    fn empty_bounds_are_dropped() {
+        let empty = IRect::new(5, 5, 5, 5);
+        let previous = [(empty, Some(1))];
+        let current = [(empty, Some(2))];
+
+        assert!(changed_regions(&BOUNDS, &previous, &current).is_empty());
+    }
+
+    #[test]
+    fn changes_are_clipped_to_the_surface() {
+        let partly_outside = IRect::new(90, 90, 110, 110);
+        let outside = IRect::new(200, 0, 210, 10);
+        let previous = [(partly_outside, Some(1)), (outside, Some(1))];
+        let current = [(partly_outside, Some(2)), (outside, Some(2))];
+
+        assert_eq!(
+            changed_regions(&BOUNDS, &previous, &current),
+            [IRect::new(90, 90, 100, 100)]
+        );
+        assert!(changed_regions(&BOUNDS, &previous[1..], &current[1..]).is_empty());
+    }
+}
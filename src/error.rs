@@ -0,0 +1,76 @@
+use std::fmt;
+
+use windows::{
+    core::HRESULT,
+    Win32::Graphics::Dxgi::{
+        DXGI_ERROR_DEVICE_HUNG, DXGI_ERROR_DEVICE_REMOVED, DXGI_ERROR_DEVICE_RESET,
+        DXGI_ERROR_DRIVER_INTERNAL_ERROR,
+    },
+};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    UnsupportedWindowHandle,
+    NoSuitableAdapter,
+    DeviceRemoved(HRESULT),
+    ContextCreationFailed,
+    SurfaceWrapFailed,
+    SurfaceCreationFailed,
+    ProtectedContentUnsupported,
+    InvalidSwapChainOptions(String),
+    HdrUnsupported,
+    InvalidIccProfile,
+    ContextLost,
+    Dxgi(HRESULT),
+}
+impl Error {
+    pub fn is_device_removed(&self) -> bool {
+        matches!(self, Self::DeviceRemoved(_))
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedWindowHandle => f.write_str("window handle is not a Win32 handle"),
+            Self::NoSuitableAdapter => f.write_str("no adapter supports Direct3D 12"),
+            Self::DeviceRemoved(reason) => write!(f, "device removed: {}", reason.message()),
+            Self::ContextCreationFailed => f.write_str("failed to create the Skia direct context"),
+            Self::SurfaceWrapFailed => f.write_str("failed to wrap a swap chain buffer"),
+            Self::SurfaceCreationFailed => f.write_str("failed to create an offscreen surface"),
+            Self::ProtectedContentUnsupported => {
+                f.write_str("the adapter does not support protected content")
+            }
+            Self::InvalidSwapChainOptions(message) => {
+                write!(f, "invalid swap chain options: {message}")
+            }
+            Self::HdrUnsupported => {
+                f.write_str("the output does not support the requested HDR color space")
+            }
+            Self::InvalidIccProfile => {
+                f.write_str("the ICC profile is not a supported RGB matrix/TRC profile")
+            }
+            Self::ContextLost => f.write_str("the Skia context has been released"),
+            Self::Dxgi(code) => write!(f, "{code}: {}", code.message()),
+        }
+    }
+}
+impl std::error::Error for Error {}
+
+impl From<HRESULT> for Error {
+    fn from(code: HRESULT) -> Self {
+        match code {
+            DXGI_ERROR_DEVICE_REMOVED
+            | DXGI_ERROR_DEVICE_RESET
+            | DXGI_ERROR_DEVICE_HUNG
+            | DXGI_ERROR_DRIVER_INTERNAL_ERROR => Self::DeviceRemoved(code),
+            _ => Self::Dxgi(code),
+        }
+    }
+}
+impl From<windows::core::Error> for Error {
+    fn from(error: windows::core::Error) -> Self {
+        error.code().into()
+    }
+}
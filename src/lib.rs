@@ -1,11 +1,28 @@
 mod d3d12;
+mod error;
 #[cfg(feature = "windows-ui-composition")]
 mod windows_ui_composition;
 
 pub use d3d12::{
-    swap_chain::{HwndSwapChain, SwapChain},
-    Backend,
+    adapter::{enumerate_adapters, AdapterInfo, AdapterKind, AdapterPreference},
+    color_space::SurfaceColorSpace,
+    context_options::ContextOptions,
+    debug::{DebugOptions, MessageSeverity},
+    dred::{Allocation, Breadcrumbs, DeviceRemovedReport, PageFault},
+    swap_chain::{
+        damage::{Damage, Frame},
+        dirty_rects::ScrollRect,
+        hdr::{Hdr10Metadata, HdrMode},
+        options::{
+            AlphaMode, PixelFormat, PresentMode, Scaling, SwapChainKind, SwapChainOptions,
+            SwapEffect,
+        },
+        recording::FrameRegions,
+        HwndSwapChain, PresentOutcome, SwapChain,
+    },
+    Backend, BackendBuilder, DeviceEvent,
 };
+pub use error::{Error, Result};
 
 #[cfg(feature = "windows-ui-composition")]
 pub use windows_ui_composition::{CompositionBackend, CompositionSwapChain, CompositionTarget};
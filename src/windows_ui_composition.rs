@@ -1,23 +1,38 @@
+use std::time::Duration;
+
 use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
-use skia_safe::{Canvas, Surface};
+use skia_safe::{Canvas, IRect, Surface};
 use windows::{
     core::Interface,
     System::DispatcherQueueController,
     Win32::{
-        Foundation::HWND,
+        Foundation::{COLORREF, HWND},
         System::WinRT::{
             Composition::{ICompositorDesktopInterop, ICompositorInterop},
             CreateDispatcherQueueController, DispatcherQueueOptions,
             DISPATCHERQUEUE_THREAD_APARTMENTTYPE, DISPATCHERQUEUE_THREAD_TYPE, DQTAT_COM_NONE,
             DQTYPE_THREAD_CURRENT,
         },
+        UI::WindowsAndMessaging::{
+            GetWindowLongW, SetLayeredWindowAttributes, SetWindowLongW, GWL_EXSTYLE, LWA_ALPHA,
+            WS_EX_LAYERED, WS_EX_NOREDIRECTIONBITMAP, WS_EX_TRANSPARENT,
+        },
     },
     UI::Composition::{Compositor, Desktop::DesktopWindowTarget, ICompositionSurface},
 };
 
-use crate::d3d12::{
-    swap_chain::{SwapChain, SwapChainState},
-    Backend,
+use crate::{
+    d3d12::{
+        swap_chain::{
+            damage::Frame,
+            dirty_rects::ScrollRect,
+            options::{PresentMode, SwapChainOptions},
+            recording::FrameRegions,
+            PresentOutcome, SwapChain, SwapChainState,
+        },
+        Backend, BackendBuilder,
+    },
+    error::{Error, Result},
 };
 
 pub struct CompositionBackend {
@@ -25,40 +40,55 @@ pub struct CompositionBackend {
     d3d12: Backend,
 }
 impl CompositionBackend {
-    pub fn new() -> windows::core::Result<Self> {
+    pub fn new() -> Result<Self> {
+        Self::from_backend(Backend::new()?)
+    }
+    pub fn with_builder(builder: BackendBuilder) -> Result<Self> {
+        Self::from_backend(builder.build()?)
+    }
+    pub fn from_backend(backend: Backend) -> Result<Self> {
         Ok(Self {
             _dispatcher_queue_controller: create_dispatcher_queue_controller_for_current_thread()?,
-            d3d12: Backend::new()?,
+            d3d12: backend,
         })
     }
+    pub fn backend(&self) -> &Backend {
+        &self.d3d12
+    }
+    pub fn backend_mut(&mut self) -> &mut Backend {
+        &mut self.d3d12
+    }
     pub fn create_swap_chain(
         &mut self,
         width: u32,
         height: u32,
-    ) -> windows::core::Result<CompositionSwapChain> {
-        Ok(CompositionSwapChain::new(
-            self.d3d12
-                .create_swap_chain_for_composition(width, height)?,
-        ))
+        options: SwapChainOptions,
+    ) -> Result<CompositionSwapChain> {
+        let swap_chain = self
+            .d3d12
+            .create_swap_chain_for_composition(width, height, &options)?;
+
+        Ok(CompositionSwapChain::new(options, swap_chain))
     }
 }
 
 pub struct CompositionTarget {
     pub compositor: Compositor,
     pub desktop_window_target: DesktopWindowTarget,
+    hwnd: HWND,
 }
 impl CompositionTarget {
-    pub fn with_window<W: HasRawWindowHandle>(window: &W) -> windows::core::Result<Self> {
+    pub fn with_window<W: HasRawWindowHandle>(window: &W) -> Result<Self> {
         Self::with_raw_window_handle(window.raw_window_handle())
     }
-    pub fn with_raw_window_handle(window_handle: RawWindowHandle) -> windows::core::Result<Self> {
+    pub fn with_raw_window_handle(window_handle: RawWindowHandle) -> Result<Self> {
         let hwnd = match window_handle {
             RawWindowHandle::Win32(window_handle) => HWND(window_handle.hwnd as _),
-            _ => panic!("not win32"),
+            _ => return Err(Error::UnsupportedWindowHandle),
         };
         Self::with_hwnd(hwnd)
     }
-    pub fn with_hwnd(hwnd: HWND) -> windows::core::Result<Self> {
+    pub fn with_hwnd(hwnd: HWND) -> Result<Self> {
         let compositor = Compositor::new()?;
         let compositor_desktop_interop: ICompositorDesktopInterop = compositor.cast()?;
         let desktop_window_target =
@@ -67,72 +97,153 @@ impl CompositionTarget {
         Ok(Self {
             compositor,
             desktop_window_target,
+            hwnd,
         })
     }
+    // Only premultiplied swap chain content shows through, and only if the window was created
+    // with WS_EX_NOREDIRECTIONBITMAP (winit's `with_no_redirection_bitmap`).
+    pub fn with_transparent_window<W: HasRawWindowHandle>(
+        window: &W,
+        click_through: bool,
+    ) -> Result<Self> {
+        let target = Self::with_window(window)?;
+        if target.ex_style() & WS_EX_NOREDIRECTIONBITMAP.0 == 0 {
+            log::warn!("window has a redirection bitmap, transparent areas will not show through");
+        }
+        target.set_click_through(click_through)?;
+        Ok(target)
+    }
+    // Click-through windows pass mouse input to whatever is below them.
+    pub fn set_click_through(&self, click_through: bool) -> Result<()> {
+        let click_through_style = (WS_EX_LAYERED | WS_EX_TRANSPARENT).0;
+
+        let ex_style = if click_through {
+            self.ex_style() | click_through_style
+        } else {
+            self.ex_style() & !click_through_style
+        };
+        unsafe { SetWindowLongW(self.hwnd, GWL_EXSTYLE, ex_style as i32) };
+
+        if click_through {
+            // Layered windows stay hidden until their attributes are set.
+            unsafe { SetLayeredWindowAttributes(self.hwnd, COLORREF(0), 255, LWA_ALPHA) }?;
+        }
+        Ok(())
+    }
+    fn ex_style(&self) -> u32 {
+        unsafe { GetWindowLongW(self.hwnd, GWL_EXSTYLE) as u32 }
+    }
     pub fn create_surface(
         &self,
         swap_chain: &CompositionSwapChain,
-    ) -> windows::core::Result<Option<ICompositionSurface>> {
+    ) -> Result<Option<ICompositionSurface>> {
         swap_chain
-            .0
+            .swap_chain
             .get_active()
             .map(|swap_chain| self.create_surface_internal(swap_chain))
             .transpose()
     }
-    fn create_surface_internal(
-        &self,
-        swap_chain: &SwapChain,
-    ) -> windows::core::Result<ICompositionSurface> {
+    fn create_surface_internal(&self, swap_chain: &SwapChain) -> Result<ICompositionSurface> {
         let compositor_interop: ICompositorInterop = self.compositor.cast()?;
 
-        unsafe { compositor_interop.CreateCompositionSurfaceForSwapChain(&swap_chain.swap_chain) }
+        Ok(unsafe {
+            compositor_interop.CreateCompositionSurfaceForSwapChain(&swap_chain.swap_chain)
+        }?)
     }
 }
 
-pub struct CompositionSwapChain(SwapChainState);
+pub struct CompositionSwapChain {
+    options: SwapChainOptions,
+    swap_chain: SwapChainState,
+}
 impl CompositionSwapChain {
-    fn new(swap_chain: SwapChain) -> Self {
-        Self(SwapChainState::Active(swap_chain))
+    fn new(options: SwapChainOptions, swap_chain: SwapChain) -> Self {
+        Self {
+            options,
+            swap_chain: SwapChainState::Active(swap_chain),
+        }
     }
     pub fn resize(&mut self, env: &mut CompositionBackend, width: u32, height: u32) {
-        self.0.resize(&mut env.d3d12, width, height);
+        self.swap_chain.resize(&mut env.d3d12, width, height);
     }
     pub fn new_composition_surface(
         &mut self,
         env: &mut CompositionBackend,
         target: &CompositionTarget,
-    ) -> windows::core::Result<Option<ICompositionSurface>> {
-        if let Some((width, height)) = self.0.needs_resize() {
-            env.d3d12.recreate_context_if_needed()?;
+    ) -> Result<Option<ICompositionSurface>> {
+        env.d3d12.recreate_context_if_needed()?;
+        self.swap_chain.invalidate_if_stale(&env.d3d12);
 
-            let swap_chain = env.d3d12.create_swap_chain_for_composition(width, height)?;
+        if let Some((width, height)) = self.swap_chain.needs_resize() {
+            let swap_chain =
+                env.d3d12
+                    .create_swap_chain_for_composition(width, height, &self.options)?;
             let surface = target.create_surface_internal(&swap_chain)?;
 
-            self.0 = SwapChainState::Active(swap_chain);
+            self.swap_chain = SwapChainState::Active(swap_chain);
 
             Ok(Some(surface))
         } else {
             Ok(None)
         }
     }
+    pub fn draw_recorded(
+        &mut self,
+        env: &mut CompositionBackend,
+        f: impl FnOnce(&Canvas),
+    ) -> Result<PresentOutcome> {
+        self.swap_chain
+            .draw_recorded_regions(&mut env.d3d12, |regions| regions.whole(f))
+    }
+    pub fn draw_recorded_regions(
+        &mut self,
+        env: &mut CompositionBackend,
+        f: impl FnOnce(&mut FrameRegions),
+    ) -> Result<PresentOutcome> {
+        self.swap_chain.draw_recorded_regions(&mut env.d3d12, f)
+    }
+    pub fn frame(&self) -> Frame {
+        self.swap_chain.frame()
+    }
     pub fn draw(
         &mut self,
         env: &mut CompositionBackend,
         f: impl FnMut(&Canvas),
-    ) -> windows::core::Result<()> {
-        self.0
+    ) -> Result<PresentOutcome> {
+        self.swap_chain.draw(&mut env.d3d12, f)
+    }
+    pub fn draw_dirty(
+        &mut self,
+        env: &mut CompositionBackend,
+        dirty_rects: &[IRect],
+        scroll: Option<&ScrollRect>,
+        f: impl FnMut(&Canvas),
+    ) -> Result<PresentOutcome> {
+        self.swap_chain
+            .draw_dirty(&mut env.d3d12, dirty_rects, scroll, f)
+    }
+    pub fn get_surface(&mut self) -> Result<&mut Surface> {
+        self.swap_chain
             .get_active_mut()
-            .unwrap()
-            .draw(&mut env.d3d12, f)
-            .ok()
+            .ok_or(Error::ContextLost)?
+            .get_surface()
     }
-    pub fn unwrap_surface_mut(&mut self) -> &mut Surface {
-        self.0.get_active_mut().unwrap().get_surface()
+    pub fn present(&mut self, env: &mut CompositionBackend) -> Result<PresentOutcome> {
+        self.swap_chain.present(&mut env.d3d12)
     }
-    pub fn present(&mut self, env: &mut CompositionBackend) {
-        if let Some(swap_chain) = self.0.get_active_mut() {
-            swap_chain.present(&mut env.d3d12);
-        }
+    /// Checks whether the swap chain became visible again without presenting anything.
+    pub fn is_occluded(&mut self, env: &mut CompositionBackend) -> Result<bool> {
+        self.swap_chain.is_occluded(&mut env.d3d12)
+    }
+    pub fn present_mode(&self) -> PresentMode {
+        self.swap_chain.present_mode(&self.options)
+    }
+    pub fn wait_for_next_frame(&self, timeout: Option<Duration>) -> Result<bool> {
+        self.swap_chain.wait_for_next_frame(timeout)
+    }
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) -> Result<PresentMode> {
+        self.swap_chain
+            .set_present_mode(&mut self.options, present_mode)
     }
 }
 